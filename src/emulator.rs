@@ -1,7 +1,11 @@
 use crate::emulator::host::handle::Handle;
+use crate::emulator::host::triple_buffer::{Writer, triple_buffer};
 use crate::emulator::host::{DriverMessage, EmulatorMessage, Host};
 use crate::emulator::runtime::bus::Bus;
 use crate::emulator::runtime::cpu::CPU;
+use crate::emulator::runtime::model::Model;
+use crate::emulator::runtime::ppu::PublishedFrame;
+use crate::emulator::runtime::sgb::{self, SgbFrameBuffer};
 use crate::emulator::runtime::{Runtime, State};
 use std::{
     sync::mpsc::{Receiver, Sender, channel},
@@ -23,11 +27,29 @@ impl Emulator {
     fn new(
         cartridge: Vec<u8>,
         should_trace: bool,
+        model: Option<Model>,
         sender: Sender<EmulatorMessage>,
         receiver: Receiver<DriverMessage>,
+        frame_writer: Writer<PublishedFrame>,
+        sgb_frame_writer: Writer<SgbFrameBuffer>,
     ) -> Self {
-        let cpu = CPU::new(should_trace);
-        let bus = Bus::from_cartridge_rom(cartridge).unwrap();
+        // asking for a cgb runs dmg games in compatibility mode
+        let model = match model {
+            Some(Model::Cgb) => Model::for_cartridge_on_cgb(&cartridge),
+            Some(model) => model,
+            None => Model::for_cartridge(&cartridge),
+        };
+
+        let mut cpu = CPU::new(should_trace);
+        let mut bus = Bus::from_cartridge_rom(cartridge, model).unwrap();
+        // the cgb boot rom leaves 0x11 in A, which is how games tell which console they're on
+        if model.is_cgb_hardware() {
+            cpu.a = 0x11;
+        }
+        if let Some(ppu) = bus.ppu_mut() {
+            ppu.set_frame_writer(frame_writer);
+            ppu.set_sgb_frame_writer(sgb_frame_writer);
+        }
 
         Self {
             host: Host::new(sender, receiver),
@@ -45,6 +67,20 @@ impl Emulator {
             if let Some(message) = self.runtime.handle_current_state() {
                 self.host.emit_message(message);
             }
+
+            if let Some(ly) = self.runtime.take_lcd_disabled_outside_vblank() {
+                let cycle = self.runtime.bus_mut().cycles();
+                self.host
+                    .emit_message(EmulatorMessage::LcdDisabledOutsideVBlank { ly, cycle });
+            }
+
+            if let Some(frame) = self.runtime.take_completed_frame() {
+                self.host.save_pending_screenshot(&mut self.runtime);
+                self.host.record_frame(&mut self.runtime);
+                let cycle = self.runtime.bus_mut().cycles();
+                self.host
+                    .emit_message(EmulatorMessage::FrameCompleted { frame, cycle });
+            }
         }
     }
 
@@ -55,11 +91,22 @@ impl Emulator {
     // there is also the fact that we don't hand out the whole emulator instance (which would be the expected
     // `Self` in a `new` method), but only a `Handle`.
     // that's why we chose to highlight this quirk by having a pub `init` method instead.
-    pub fn init(cartridge: Vec<u8>, should_trace: bool) -> Handle {
+    // `model` is picked from the cartridge header unless given
+    pub fn init(cartridge: Vec<u8>, should_trace: bool, model: Option<Model>) -> Handle {
         let (driver_tx, driver_rx) = channel();
         let (emulator_tx, emulator_rx) = channel();
+        let (frame_writer, frame_reader) = triple_buffer(PublishedFrame::default());
+        let (sgb_frame_writer, sgb_frame_reader) = triple_buffer(sgb::new_buffer());
 
-        let emulator = Self::new(cartridge, should_trace, emulator_tx, driver_rx);
+        let emulator = Self::new(
+            cartridge,
+            should_trace,
+            model,
+            emulator_tx,
+            driver_rx,
+            frame_writer,
+            sgb_frame_writer,
+        );
         // fixme: instead of cloning a mutable arc to the frontend, we should have the host module
         // deal with an abstraction to these submodules
         // let cpu_arc = emulator.cpu.clone();
@@ -73,6 +120,8 @@ impl Emulator {
         Handle {
             tx: driver_tx,
             rx: emulator_rx,
+            frames: frame_reader,
+            sgb_frames: sgb_frame_reader,
         }
     }
 }
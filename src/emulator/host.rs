@@ -1,35 +1,193 @@
-use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, Sender, SyncSender, TryRecvError};
 
+use crate::emulator::runtime::bus::io::apu::{Apu, Sample};
+use crate::emulator::runtime::bus::io::joypad::{Button, Input, Joypad};
+use crate::emulator::runtime::bus::observer::Observer;
+use crate::emulator::runtime::ppu::debug;
+use crate::emulator::runtime::ppu::overlay::Overlays;
+use crate::emulator::runtime::ppu::palette::Palettes;
+use crate::emulator::runtime::ppu::{Layers, Renderer};
 use crate::emulator::runtime::{Runtime, State};
 
 pub mod handle;
 pub mod policy;
+pub mod postprocess;
+pub mod recording;
+pub mod screenshot;
+pub mod triple_buffer;
+pub mod wav;
 
 pub enum DriverMessage {
     Run(Option<policy::Policy>),
     PauseRequest,
+    Observe(Arc<dyn Observer>),
+    ClearObservers,
+    // reads `length` bytes starting at `start`. `bank` selects a specific bank instead of
+    // the one that's currently mapped. answered with `EmulatorMessage::Memory`.
+    Peek {
+        start: u16,
+        length: u16,
+        bank: Option<u16>,
+    },
+    Poke {
+        start: u16,
+        bank: Option<u16>,
+        bytes: Vec<u8>,
+    },
+    // applied once emulation reaches `cycle`, or right away if that's already passed.
+    // `EmulatorMessage::FrameCompleted` says which cycle emulation is at.
+    ButtonPress {
+        button: Button,
+        cycle: u64,
+    },
+    ButtonRelease {
+        button: Button,
+        cycle: u64,
+    },
+    SetRenderer(Renderer),
+    SetPalettes(Palettes),
+    SetLayers(Layers),
+    SetOverlays(Overlays),
+    // saves the next completed frame as png, or the current one if paused. `scale` is an
+    // integer factor, 1 being 160x144. answered with `EmulatorMessage::ScreenshotSaved`.
+    Screenshot {
+        path: PathBuf,
+        scale: usize,
+    },
+    // records every frame from now on to an animated png at `path`, and the audio to a
+    // wav file next to it, until stopped.
+    // answered with `EmulatorMessage::RecordingStopped`.
+    StartRecording(PathBuf),
+    StopRecording,
+    // renders a view of vram or oam, answered with `EmulatorMessage::DebugView`
+    RenderDebugView(debug::View),
+    // sends batches of stereo samples at `apu::SAMPLE_RATE` from now on, replacing whoever
+    // was listening before. batches the receiver doesn't take in time are dropped.
+    StreamAudio(SyncSender<Vec<Sample>>),
 }
 
 #[derive(Debug, PartialEq)]
 pub enum EmulatorMessage {
     Paused,
     Running,
+    // the ppu entered vblank. `frame` counts the frames drawn since power on, `cycle` is
+    // the t-cycle it happened at, which is what button presses are timed against.
+    FrameCompleted {
+        frame: u64,
+        cycle: u64,
+    },
+    // unreadable addresses are reported as 0xFF, like open bus on hardware
+    Memory {
+        start: u16,
+        bank: Option<u16>,
+        bytes: Vec<u8>,
+    },
+    DebugView(debug::Rendered),
+    // the game turned the lcd off on scanline `ly`, outside of vblank. that's said to
+    // damage real hardware, here it just works.
+    LcdDisabledOutsideVBlank {
+        ly: u8,
+        cycle: u64,
+    },
+    ScreenshotSaved(PathBuf),
+    RecordingStopped {
+        path: PathBuf,
+        frames: u32,
+    },
 }
 
 pub struct Host {
     sender: Sender<EmulatorMessage>,
     receiver: Receiver<DriverMessage>,
+    // waiting for the frame that's being drawn to be completed
+    screenshot: Option<(PathBuf, usize)>,
+    recording: Option<recording::Recording>,
 }
 
 impl Host {
     pub fn new(sender: Sender<EmulatorMessage>, receiver: Receiver<DriverMessage>) -> Self {
-        Self { sender, receiver }
+        Self {
+            sender,
+            receiver,
+            screenshot: None,
+            recording: None,
+        }
     }
 
     pub fn emit_message(&mut self, message: EmulatorMessage) {
         self.sender.send(message).unwrap();
     }
 
+    fn queue_input(runtime: &mut Runtime, input: Input) {
+        match runtime.bus_mut().device_mut::<Joypad>() {
+            Some(joypad) => joypad.queue(input),
+            None => eprintln!("no joypad attached, dropping {:?}", input),
+        }
+    }
+
+    // takes the screenshot that was asked for, once there's a frame to take it of
+    pub fn save_pending_screenshot(&mut self, runtime: &mut Runtime) {
+        let Some((path, scale)) = self.screenshot.take() else {
+            return;
+        };
+        let Some(ppu) = runtime.bus_mut().ppu() else {
+            return;
+        };
+        match screenshot::save(ppu.frame(), scale, &path) {
+            Ok(()) => self.emit_message(EmulatorMessage::ScreenshotSaved(path)),
+            Err(e) => eprintln!("failed to save screenshot to {:?}: {}", path, e),
+        }
+    }
+
+    // adds the frame the ppu just completed to the recording, if there is one
+    pub fn record_frame(&mut self, runtime: &mut Runtime) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+        let bus = runtime.bus_mut();
+        // the samples belong to the frame before this one, so they go in first
+        let samples = bus
+            .device_mut::<Apu>()
+            .map(Apu::take_recorded_samples)
+            .unwrap_or_default();
+        let elapsed = bus.elapsed();
+        let Some(ppu) = bus.ppu() else {
+            return;
+        };
+        let result = recording
+            .push_samples(&samples)
+            .and_then(|()| recording.push_frame(ppu.frame(), elapsed));
+        if let Err(e) = result {
+            eprintln!(
+                "failed to record to {:?}, stopping: {}",
+                recording.path(),
+                e
+            );
+            self.recording = None;
+            Self::record_samples(runtime, false);
+        }
+    }
+
+    fn record_samples(runtime: &mut Runtime, is_recording: bool) {
+        if let Some(apu) = runtime.bus_mut().device_mut::<Apu>() {
+            apu.set_is_recording(is_recording);
+        }
+    }
+
+    fn stop_recording(&mut self, runtime: &mut Runtime) {
+        let Some(recording) = self.recording.take() else {
+            return;
+        };
+        Self::record_samples(runtime, false);
+        let path = recording.path().to_path_buf();
+        match recording.finish() {
+            Ok(frames) => self.emit_message(EmulatorMessage::RecordingStopped { path, frames }),
+            Err(e) => eprintln!("failed to finish recording to {:?}: {}", path, e),
+        }
+    }
+
     pub fn handle_driver_message(&mut self, runtime: &mut Runtime) {
         let message = match self.receiver.try_recv() {
             Ok(m) => m,
@@ -50,6 +208,103 @@ impl Host {
                 runtime.transition_to(State::Paused, None);
                 self.emit_message(EmulatorMessage::Paused);
             }
+            DriverMessage::Observe(observer) => runtime.bus_mut().observe(observer),
+            DriverMessage::ClearObservers => runtime.bus_mut().clear_observers(),
+            DriverMessage::Peek {
+                start,
+                length,
+                bank,
+            } => {
+                let bus = runtime.bus_mut();
+                let bytes = (0..length)
+                    .map(|offset| {
+                        let addr = start.wrapping_add(offset);
+                        match bank {
+                            Some(b) => bus.peek_bank(b, addr),
+                            None => bus.peek(addr),
+                        }
+                        .unwrap_or(0xFF)
+                    })
+                    .collect();
+                self.emit_message(EmulatorMessage::Memory { start, bank, bytes });
+            }
+            DriverMessage::ButtonPress { button, cycle } => Self::queue_input(
+                runtime,
+                Input {
+                    button,
+                    is_pressed: true,
+                    cycle,
+                },
+            ),
+            DriverMessage::ButtonRelease { button, cycle } => Self::queue_input(
+                runtime,
+                Input {
+                    button,
+                    is_pressed: false,
+                    cycle,
+                },
+            ),
+            DriverMessage::SetRenderer(renderer) => {
+                if let Some(ppu) = runtime.bus_mut().ppu_mut() {
+                    ppu.set_renderer(renderer);
+                }
+            }
+            DriverMessage::SetPalettes(palettes) => {
+                if let Some(ppu) = runtime.bus_mut().ppu_mut() {
+                    ppu.set_palettes(palettes);
+                }
+            }
+            DriverMessage::SetLayers(layers) => {
+                if let Some(ppu) = runtime.bus_mut().ppu_mut() {
+                    ppu.set_layers(layers);
+                }
+            }
+            DriverMessage::SetOverlays(overlays) => {
+                if let Some(ppu) = runtime.bus_mut().ppu_mut() {
+                    ppu.set_overlays(overlays);
+                }
+            }
+            DriverMessage::Screenshot { path, scale } => {
+                self.screenshot = Some((path, scale));
+                if runtime.is_paused() {
+                    self.save_pending_screenshot(runtime);
+                }
+            }
+            DriverMessage::StartRecording(path) => {
+                // only one recording at a time, starting another one ends the last
+                self.stop_recording(runtime);
+                match recording::Recording::start(&path) {
+                    Ok(recording) => {
+                        self.recording = Some(recording);
+                        Self::record_samples(runtime, true);
+                    }
+                    Err(e) => eprintln!("failed to start recording to {:?}: {}", path, e),
+                }
+            }
+            DriverMessage::StopRecording => self.stop_recording(runtime),
+            DriverMessage::StreamAudio(sender) => match runtime.bus_mut().device_mut::<Apu>() {
+                Some(apu) => apu.set_sample_sender(sender),
+                None => eprintln!("no apu attached, there's no audio to stream"),
+            },
+            DriverMessage::RenderDebugView(view) => {
+                if let Some(ppu) = runtime.bus_mut().ppu() {
+                    let rendered = debug::render(ppu, view);
+                    self.emit_message(EmulatorMessage::DebugView(rendered));
+                }
+            }
+            DriverMessage::Poke { start, bank, bytes } => {
+                let bus = runtime.bus_mut();
+                for (offset, content) in bytes.into_iter().enumerate() {
+                    let addr = start.wrapping_add(offset as u16);
+                    let result = match bank {
+                        Some(b) => bus.poke_bank(b, addr, content),
+                        None => bus.poke(addr, content),
+                    };
+                    if let Err(e) = result {
+                        eprintln!("{}", e);
+                    }
+                }
+            }
         }
     }
 }
@@ -1,8 +1,15 @@
 use std::sync::mpsc::{Receiver, Sender};
 
+use crate::emulator::host::triple_buffer::Reader;
 use crate::emulator::host::{DriverMessage, EmulatorMessage};
+use crate::emulator::runtime::ppu::PublishedFrame;
+use crate::emulator::runtime::sgb::SgbFrameBuffer;
 
 pub struct Handle {
     pub tx: Sender<DriverMessage>,
     pub rx: Receiver<EmulatorMessage>,
+    // the latest frame the ppu completed, without going through the message channel
+    pub frames: Reader<PublishedFrame>,
+    // the same frames with the sgb border around them, only updated in sgb mode
+    pub sgb_frames: Reader<SgbFrameBuffer>,
 }
@@ -0,0 +1,410 @@
+use std::{fmt, str::FromStr};
+
+use crate::emulator::runtime::ppu::debug::Image;
+use crate::emulator::runtime::ppu::{FrameBuffer, HEIGHT, RGBA8888, WIDTH};
+
+// optional processing of frames on their way to the screen, done on the frontend's side so
+// the core always hands out exactly what the ppu drew. runs in this order:
+//
+// - colour correction, which maps cgb colours to what the lcd actually shows. games were
+//   made to look right on that screen, raw rgb555 comes out too saturated and too dark.
+// - blending with the previous frames, for the ghosting of the real lcds. games that
+//   flicker objects every other frame rely on it for transparency.
+// - scaling up, either by repeating pixels or with a pixel art scaler.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaler {
+    // repeats every pixel n x n times
+    Integer(usize),
+    // scale2x / scale3x (aka epx / advmame), which round off diagonal edges
+    Scale2x,
+    Scale3x,
+    // a simplified first level xbr, which looks at a wider neighbourhood to find edges
+    // and blends along them instead of just picking neighbours
+    Xbr2x,
+}
+
+impl Default for Scaler {
+    fn default() -> Self {
+        Scaler::Integer(1)
+    }
+}
+
+impl Scaler {
+    pub fn factor(self) -> usize {
+        match self {
+            Scaler::Integer(factor) => factor.max(1),
+            Scaler::Scale2x | Scaler::Xbr2x => 2,
+            Scaler::Scale3x => 3,
+        }
+    }
+}
+
+impl fmt::Display for Scaler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scaler::Integer(factor) => write!(f, "{}x", factor),
+            Scaler::Scale2x => write!(f, "scale2x"),
+            Scaler::Scale3x => write!(f, "scale3x"),
+            Scaler::Xbr2x => write!(f, "xbr2x"),
+        }
+    }
+}
+
+impl FromStr for Scaler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scale2x" => Ok(Scaler::Scale2x),
+            "scale3x" => Ok(Scaler::Scale3x),
+            "xbr2x" => Ok(Scaler::Xbr2x),
+            _ => s
+                .strip_suffix('x')
+                .and_then(|factor| factor.parse::<usize>().ok())
+                .filter(|&factor| factor > 0)
+                .map(Scaler::Integer)
+                .ok_or_else(|| format!("unknown scaler {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub is_color_corrected: bool,
+    // how much of the previous frame is left over in the next one, 0 turns blending off
+    pub persistence: f32,
+    pub scaler: Scaler,
+}
+
+impl Settings {
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::default()
+    }
+}
+
+pub struct PostProcess {
+    settings: Settings,
+    // the last frame after colour correction and blending, blending keeps going from there
+    current: Option<FrameBuffer>,
+}
+
+impl PostProcess {
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            settings,
+            current: None,
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    // colour corrects a frame and blends it with the previous ones. expects to see every
+    // frame, otherwise blending fades slower than it should
+    pub fn push(&mut self, frame: &FrameBuffer) {
+        let is_color_corrected = self.settings.is_color_corrected;
+        let correct = |pixel| match is_color_corrected {
+            true => correct_color(pixel),
+            false => pixel,
+        };
+
+        match &mut self.current {
+            Some(current) => {
+                // weight of the previous frame, out of 256. 0 keeps none of it
+                let persistence = self.settings.persistence.clamp(0.0, 1.0);
+                let weight = (persistence * 256.0).min(255.0) as u32;
+                for (old, &new) in current.iter_mut().zip(frame.iter()) {
+                    *old = blend(correct(new), *old, weight);
+                }
+            }
+            None => {
+                let mut current = frame.clone();
+                for pixel in current.iter_mut() {
+                    *pixel = correct(*pixel);
+                }
+                self.current = Some(current);
+            }
+        }
+    }
+
+    // the last frame pushed, scaled up. scaling is by far the slowest step, so it's only
+    // done when the frame is actually shown or saved
+    pub fn scaled(&self) -> Option<Image> {
+        let pixels = &self.current.as_ref()?[..];
+        let scale = self.settings.scaler.factor();
+        Some(Image {
+            width: WIDTH * scale,
+            height: HEIGHT * scale,
+            pixels: match self.settings.scaler {
+                Scaler::Integer(_) => scale_integer(pixels, scale),
+                Scaler::Scale2x => scale2x(pixels),
+                Scaler::Scale3x => scale3x(pixels),
+                Scaler::Xbr2x => xbr2x(pixels),
+            },
+        })
+    }
+}
+
+fn channels(color: RGBA8888) -> [u32; 4] {
+    color.to_be_bytes().map(|channel| channel as u32)
+}
+
+fn from_channels([r, g, b, a]: [u32; 4]) -> RGBA8888 {
+    u32::from_be_bytes([r as u8, g as u8, b as u8, a as u8])
+}
+
+// the usual cgb lcd approximation: every channel bleeds into the others and the
+// brightest colours top out below full white. works on the 5 bit colours the ppu
+// started out with.
+fn correct_color(color: RGBA8888) -> RGBA8888 {
+    let [r, g, b, a] = channels(color).map(|channel| channel >> 3);
+    let r_out = (r * 26 + g * 4 + b * 2).min(960) >> 2;
+    let g_out = (g * 24 + b * 8).min(960) >> 2;
+    let b_out = (r * 6 + g * 4 + b * 22).min(960) >> 2;
+    from_channels([r_out, g_out, b_out, (a << 3) | (a >> 2)])
+}
+
+// mixes `old` into `new`, `weight` being how much of `old` is kept out of 256
+fn blend(new: RGBA8888, old: RGBA8888, weight: u32) -> RGBA8888 {
+    let (new, old) = (channels(new), channels(old));
+    from_channels(std::array::from_fn(|i| {
+        (new[i] * (256 - weight) + old[i] * weight) >> 8
+    }))
+}
+
+// a pixel of the frame, with coordinates outside of it clamped to the nearest edge
+fn pixel(pixels: &[RGBA8888], x: isize, y: isize) -> RGBA8888 {
+    let x = x.clamp(0, WIDTH as isize - 1) as usize;
+    let y = y.clamp(0, HEIGHT as isize - 1) as usize;
+    pixels[y * WIDTH + x]
+}
+
+fn scale_integer(pixels: &[RGBA8888], scale: usize) -> Vec<RGBA8888> {
+    let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+    for row in pixels.chunks_exact(WIDTH) {
+        let start = scaled.len();
+        for &color in row {
+            scaled.extend(std::iter::repeat_n(color, scale));
+        }
+        for _ in 1..scale {
+            scaled.extend_from_within(start..start + WIDTH * scale);
+        }
+    }
+    scaled
+}
+
+// neighbours are named like this, e being the pixel that's scaled up:
+//
+//     a b c
+//     d e f
+//     g h i
+fn scale2x(pixels: &[RGBA8888]) -> Vec<RGBA8888> {
+    let width = WIDTH * 2;
+    let mut scaled = vec![0; pixels.len() * 4];
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let at = |dx: isize, dy: isize| pixel(pixels, x as isize + dx, y as isize + dy);
+            let (b, d, e, f, h) = (at(0, -1), at(-1, 0), at(0, 0), at(1, 0), at(0, 1));
+
+            let mut out = [e; 4];
+            if b != h && d != f {
+                if d == b {
+                    out[0] = d;
+                }
+                if b == f {
+                    out[1] = f;
+                }
+                if d == h {
+                    out[2] = d;
+                }
+                if h == f {
+                    out[3] = f;
+                }
+            }
+
+            let top = y * 2 * width + x * 2;
+            scaled[top..top + 2].copy_from_slice(&out[..2]);
+            scaled[top + width..top + width + 2].copy_from_slice(&out[2..]);
+        }
+    }
+    scaled
+}
+
+fn scale3x(pixels: &[RGBA8888]) -> Vec<RGBA8888> {
+    let width = WIDTH * 3;
+    let mut scaled = vec![0; pixels.len() * 9];
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let at = |dx: isize, dy: isize| pixel(pixels, x as isize + dx, y as isize + dy);
+            let [a, b, c, d, e, f, g, h, i] = [
+                at(-1, -1),
+                at(0, -1),
+                at(1, -1),
+                at(-1, 0),
+                at(0, 0),
+                at(1, 0),
+                at(-1, 1),
+                at(0, 1),
+                at(1, 1),
+            ];
+
+            let mut out = [e; 9];
+            if b != h && d != f {
+                if d == b {
+                    out[0] = d;
+                }
+                if (d == b && e != c) || (b == f && e != a) {
+                    out[1] = b;
+                }
+                if b == f {
+                    out[2] = f;
+                }
+                if (d == b && e != g) || (d == h && e != a) {
+                    out[3] = d;
+                }
+                if (b == f && e != i) || (h == f && e != c) {
+                    out[5] = f;
+                }
+                if d == h {
+                    out[6] = d;
+                }
+                if (d == h && e != i) || (h == f && e != g) {
+                    out[7] = h;
+                }
+                if h == f {
+                    out[8] = f;
+                }
+            }
+
+            for row in 0..3 {
+                let start = (y * 3 + row) * width + x * 3;
+                scaled[start..start + 3].copy_from_slice(&out[row * 3..row * 3 + 3]);
+            }
+        }
+    }
+    scaled
+}
+
+// how different two colours look, weighing brightness the most like xbr does
+fn distance(first: RGBA8888, second: RGBA8888) -> u32 {
+    let [r1, g1, b1, _] = channels(first).map(|channel| channel as i32);
+    let [r2, g2, b2, _] = channels(second).map(|channel| channel as i32);
+    let (r, g, b) = (r1 - r2, g1 - g2, b1 - b2);
+
+    // yuv, scaled by 1000
+    let y = 299 * r + 587 * g + 114 * b;
+    let u = -169 * r - 331 * g + 500 * b;
+    let v = 500 * r - 419 * g - 81 * b;
+    ((48 * y.abs() + 7 * u.abs() + 6 * v.abs()) / 1000) as u32
+}
+
+// each of the four output pixels looks at the corner of the neighbourhood it's in. the
+// pattern is the same for every corner, mirrored, so offsets are given for the bottom
+// right one:
+//
+//          b  .
+//       d  e  f  f4
+//       g  h  i  i4
+//          h5 i5
+fn xbr2x(pixels: &[RGBA8888]) -> Vec<RGBA8888> {
+    let width = WIDTH * 2;
+    let mut scaled = vec![0; pixels.len() * 4];
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            for (corner, (sx, sy)) in [(-1, -1), (1, -1), (-1, 1), (1, 1)].into_iter().enumerate() {
+                let at = |dx: isize, dy: isize| {
+                    pixel(pixels, x as isize + dx * sx, y as isize + dy * sy)
+                };
+                let e = at(0, 0);
+                let (b, c, d, f, g, h, i) = (
+                    at(0, -1),
+                    at(1, -1),
+                    at(-1, 0),
+                    at(1, 0),
+                    at(-1, 1),
+                    at(0, 1),
+                    at(1, 1),
+                );
+                let (f4, i4, h5, i5) = (at(2, 0), at(2, 1), at(0, 2), at(1, 2));
+
+                let mut out = e;
+                if e != f && e != h {
+                    // there's an edge along the f-h diagonal if the pixels on each side of
+                    // it are more alike than those on each side of the e-i one
+                    let f_h = distance(e, c)
+                        + distance(e, g)
+                        + distance(i, f4)
+                        + distance(i, h5)
+                        + 4 * distance(h, f);
+                    let e_i = distance(h, d)
+                        + distance(h, i5)
+                        + distance(f, i4)
+                        + distance(f, b)
+                        + 4 * distance(e, i);
+                    if f_h < e_i {
+                        let closest = if distance(e, f) <= distance(e, h) {
+                            f
+                        } else {
+                            h
+                        };
+                        out = blend(e, closest, 128);
+                    }
+                }
+
+                let (column, row) = (corner % 2, corner / 2);
+                scaled[(y * 2 + row) * width + x * 2 + column] = out;
+            }
+        }
+    }
+    scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::runtime::ppu;
+
+    #[test]
+    fn frames_are_blended_as_they_come_and_scaled_on_demand() {
+        let mut post_process = PostProcess::new(Settings {
+            persistence: 0.5,
+            scaler: Scaler::Integer(2),
+            ..Settings::default()
+        });
+        assert!(post_process.scaled().is_none());
+
+        let mut frame = ppu::new_buffer();
+        frame.fill(0xFFFFFFFF);
+        post_process.push(&frame);
+        frame.fill(0x000000FF);
+        post_process.push(&frame);
+        post_process.push(&frame);
+
+        // half of white, then a quarter
+        let image = post_process.scaled().unwrap();
+        assert_eq!((image.width, image.height), (WIDTH * 2, HEIGHT * 2));
+        assert!(image.pixels.iter().all(|&pixel| pixel == 0x3F3F3FFF));
+    }
+
+    #[test]
+    fn without_persistence_the_last_frame_is_all_there_is() {
+        let mut post_process = PostProcess::new(Settings {
+            scaler: Scaler::Scale2x,
+            ..Settings::default()
+        });
+        let mut frame = ppu::new_buffer();
+        frame.fill(0xFFFFFFFF);
+        post_process.push(&frame);
+        frame[0] = 0x000000FF;
+        post_process.push(&frame);
+
+        let image = post_process.scaled().unwrap();
+        assert_eq!(image.pixels[0], 0x000000FF);
+        assert_eq!(image.pixels[2], 0xFFFFFFFF);
+    }
+}
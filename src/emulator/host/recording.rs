@@ -0,0 +1,316 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::emulator::host::{screenshot, wav::Wav};
+use crate::emulator::runtime::bus::CLOCK_SPEED;
+use crate::emulator::runtime::bus::io::apu::{SAMPLE_RATE, Sample};
+use crate::emulator::runtime::ppu::{
+    self, DOTS_PER_LINE, FrameBuffer, HEIGHT, LINES_PER_FRAME, WIDTH,
+};
+
+// records the frames the ppu completes to an animated png, which keeps them lossless and
+// needs nothing but the png writer we already have. every frame is shown for as long as
+// it was on the emulated lcd, going by emulated time rather than the host's clock, so the
+// recording plays back at the right speed no matter how fast emulation ran. that also
+// covers the lcd being turned off, where the last frame just stays up.
+//
+// the apu's samples go into a wav file next to it, covering the same stretch of emulated
+// time: from the first frame until the last one is done showing.
+
+// frame delays are fractions of a second with a 16 bit numerator and denominator, so
+// they're rounded to milliseconds. the rounding doesn't add up over time, since delays
+// are derived from the total time recorded so far.
+const DELAY_DENOMINATOR: u16 = 1000;
+
+// how long a frame takes with the lcd on, in t-cycles at normal speed
+const FRAME_CYCLES: u64 = DOTS_PER_LINE as u64 * LINES_PER_FRAME as u64;
+
+// where the frame count ends up in the file, which isn't known until the recording stops:
+// after the signature, the IHDR chunk and acTL's length and type
+const FRAME_COUNT_OFFSET: u64 = 8 + (12 + 13) + 8;
+
+pub struct Recording {
+    path: PathBuf,
+    file: BufWriter<File>,
+    // every frame control and frame data chunk takes a sequence number
+    sequence: u32,
+    frames: u32,
+    // the last frame, which is only written once the next one shows how long it lasted,
+    // along with the emulated time it was completed at
+    pending: Option<(FrameBuffer, u64)>,
+    // total delay written so far, in `DELAY_DENOMINATOR` units
+    written: u64,
+    audio: Wav,
+    // the emulated time of the first frame, where the audio starts too
+    started: u64,
+}
+
+impl Recording {
+    pub fn start(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = BufWriter::new(File::create(&path)?);
+        let audio = Wav::create(path.with_extension("wav"))?;
+
+        let mut head = screenshot::SIGNATURE.to_vec();
+        screenshot::write_chunk(&mut head, b"IHDR", &screenshot::header(WIDTH, HEIGHT));
+        // frame count is patched in later, 0 plays make it loop forever
+        screenshot::write_chunk(&mut head, b"acTL", &[0; 8]);
+        file.write_all(&head)?;
+
+        Ok(Self {
+            path,
+            file,
+            sequence: 0,
+            frames: 0,
+            pending: None,
+            written: 0,
+            audio,
+            started: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // `elapsed` is the emulated time the frame was completed at, see `Bus::elapsed`
+    pub fn push_frame(&mut self, frame: &FrameBuffer, elapsed: u64) -> io::Result<()> {
+        match self.pending.take() {
+            Some((mut pending, _)) => {
+                self.write_frame(&pending, elapsed)?;
+                pending.copy_from_slice(&frame[..]);
+                self.pending = Some((pending, elapsed));
+            }
+            None => {
+                // the recording starts with the first frame
+                self.written = delay_units(elapsed);
+                self.started = elapsed;
+                self.pending = Some((frame.clone(), elapsed));
+            }
+        }
+        Ok(())
+    }
+
+    // samples the apu mixed since the last frame. the ones from before the first frame
+    // are dropped, the recording hasn't started yet.
+    pub fn push_samples(&mut self, samples: &[Sample]) -> io::Result<()> {
+        if self.pending.is_none() {
+            return Ok(());
+        }
+        self.audio.push(samples)
+    }
+
+    // writes out the last frame and the frame count. returns the number of frames recorded.
+    pub fn finish(mut self) -> io::Result<u32> {
+        let (frame, started) = self
+            .pending
+            .take()
+            .unwrap_or_else(|| (ppu::new_buffer(), 0));
+        let ended = started + FRAME_CYCLES;
+        self.write_frame(&frame, ended)?;
+
+        let mut tail = Vec::new();
+        screenshot::write_chunk(&mut tail, b"IEND", &[]);
+        self.file.write_all(&tail)?;
+
+        let mut control = self.frames.to_be_bytes().to_vec();
+        control.extend_from_slice(&0u32.to_be_bytes());
+        let mut crc_input = b"acTL".to_vec();
+        crc_input.extend_from_slice(&control);
+        control.extend_from_slice(&screenshot::crc32(&crc_input).to_be_bytes());
+
+        self.file.seek(SeekFrom::Start(FRAME_COUNT_OFFSET))?;
+        self.file.write_all(&control)?;
+        self.file.flush()?;
+
+        // nothing was mixed for the time the last frame is shown, that's silence
+        let length = (ended - self.started) * SAMPLE_RATE / CLOCK_SPEED;
+        let missing = length.saturating_sub(self.audio.samples() as u64);
+        self.audio.push(&vec![[0; 2]; missing as usize])?;
+        self.audio.finish()?;
+        Ok(self.frames)
+    }
+
+    // writes `frame`, shown until the emulated time `ended`
+    fn write_frame(&mut self, frame: &FrameBuffer, ended: u64) -> io::Result<()> {
+        let end = delay_units(ended);
+        let mut delay = end.saturating_sub(self.written);
+        self.written = self.written.max(end);
+
+        let data = screenshot::zlib_stored(&screenshot::raw_image(&frame[..], WIDTH, 1));
+        // a delay that doesn't fit into 16 bits, like from the lcd being off for over a
+        // minute, is split up into the same frame shown several times
+        loop {
+            let part = delay.min(u16::MAX as u64) as u16;
+            self.write_frame_chunks(&data, part)?;
+            delay -= part as u64;
+            if delay == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn write_frame_chunks(&mut self, data: &[u8], delay: u16) -> io::Result<()> {
+        let mut control = Vec::with_capacity(26);
+        control.extend_from_slice(&self.sequence.to_be_bytes());
+        control.extend_from_slice(&(WIDTH as u32).to_be_bytes());
+        control.extend_from_slice(&(HEIGHT as u32).to_be_bytes());
+        // no offset, every frame covers the whole image
+        control.extend_from_slice(&[0; 8]);
+        control.extend_from_slice(&delay.to_be_bytes());
+        control.extend_from_slice(&DELAY_DENOMINATOR.to_be_bytes());
+        // leave the frame as is when the next one comes, which replaces it completely
+        control.extend_from_slice(&[0, 0]);
+
+        let mut chunks = Vec::with_capacity(data.len() + 64);
+        screenshot::write_chunk(&mut chunks, b"fcTL", &control);
+        self.sequence += 1;
+
+        // the first frame doubles as the still image for viewers that don't know apng
+        if self.frames == 0 {
+            screenshot::write_chunk(&mut chunks, b"IDAT", data);
+        } else {
+            let mut frame_data = Vec::with_capacity(data.len() + 4);
+            frame_data.extend_from_slice(&self.sequence.to_be_bytes());
+            frame_data.extend_from_slice(data);
+            screenshot::write_chunk(&mut chunks, b"fdAT", &frame_data);
+            self.sequence += 1;
+        }
+
+        self.frames += 1;
+        self.file.write_all(&chunks)
+    }
+}
+
+// emulated time, rounded to the nearest `DELAY_DENOMINATOR`th of a second
+fn delay_units(elapsed: u64) -> u64 {
+    (elapsed * DELAY_DENOMINATOR as u64 + CLOCK_SPEED / 2) / CLOCK_SPEED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    type Chunk = ([u8; 4], Vec<u8>);
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("bamegoy-{}-{}.png", name, std::process::id()))
+    }
+
+    // reads and removes what a recording left behind: the png and the wav's samples
+    fn take_files(path: &Path) -> (Vec<u8>, Vec<u8>) {
+        let png = fs::read(path).unwrap();
+        let wav = fs::read(path.with_extension("wav")).unwrap();
+        fs::remove_file(path).unwrap();
+        fs::remove_file(path.with_extension("wav")).unwrap();
+        (png, wav[44..].to_vec())
+    }
+
+    // records blank frames completed at the given emulated times, returning every chunk
+    fn record(name: &str, completed_at: &[u64]) -> Vec<Chunk> {
+        let path = path(name);
+        let mut recording = Recording::start(&path).unwrap();
+        let frame = ppu::new_buffer();
+        for &elapsed in completed_at {
+            recording.push_frame(&frame, elapsed).unwrap();
+        }
+        let count = recording.finish().unwrap();
+        let (png, _) = take_files(&path);
+
+        let chunks = chunks(&png);
+        assert_eq!(&chunks[1].0, b"acTL");
+        assert_eq!(chunks[1].1[..4], count.to_be_bytes());
+        assert_eq!(delays(&chunks).len(), count as usize);
+        chunks
+    }
+
+    // splits a png into its chunks, checking every crc along the way
+    fn chunks(png: &[u8]) -> Vec<Chunk> {
+        assert_eq!(png[..8], screenshot::SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (body, tail) = rest[4..].split_at(4 + length);
+            assert_eq!(tail[..4], screenshot::crc32(body).to_be_bytes());
+            chunks.push((body[..4].try_into().unwrap(), body[4..].to_vec()));
+            rest = &tail[4..];
+        }
+        chunks
+    }
+
+    fn delays(chunks: &[Chunk]) -> Vec<u16> {
+        chunks
+            .iter()
+            .filter(|(kind, _)| kind == b"fcTL")
+            .map(|(_, data)| u16::from_be_bytes([data[20], data[21]]))
+            .collect()
+    }
+
+    #[test]
+    fn frames_are_numbered_in_order() {
+        let chunks = record("order", &[0, FRAME_CYCLES, FRAME_CYCLES * 2]);
+        let kinds: Vec<_> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                b"IHDR", b"acTL", b"fcTL", b"IDAT", b"fcTL", b"fdAT", b"fcTL", b"fdAT", b"IEND"
+            ]
+        );
+
+        let sequence: Vec<_> = chunks
+            .iter()
+            .filter(|(kind, _)| kind == b"fcTL" || kind == b"fdAT")
+            .map(|(_, data)| u32::from_be_bytes(data[..4].try_into().unwrap()))
+            .collect();
+        assert_eq!(sequence, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn delays_follow_emulated_time() {
+        let chunks = record("delays", &[0, FRAME_CYCLES, FRAME_CYCLES * 2]);
+        let delays = delays(&chunks);
+        assert!(delays.iter().all(|delay| (16..=17).contains(delay)));
+        // rounding doesn't add up, three frames take as long as they did on the lcd
+        let total: u64 = delays.iter().map(|&delay| delay as u64).sum();
+        assert_eq!(total, delay_units(FRAME_CYCLES * 3));
+    }
+
+    #[test]
+    fn long_delays_are_split_up() {
+        // the lcd was off for 70 seconds after the first frame, which is 65.535 + 4.465
+        let chunks = record("split", &[0, CLOCK_SPEED * 70]);
+        assert_eq!(delays(&chunks), [u16::MAX, 4_465, 17]);
+    }
+
+    #[test]
+    fn audio_covers_the_same_time_as_the_frames() {
+        let path = path("audio");
+        let mut recording = Recording::start(&path).unwrap();
+        let frame = ppu::new_buffer();
+        // from before the first frame, so it's not part of the recording
+        recording.push_samples(&[[1, 1]; 10]).unwrap();
+        recording.push_frame(&frame, 1_000).unwrap();
+        recording.push_samples(&[[2, -2]; 800]).unwrap();
+        recording.push_frame(&frame, 1_000 + FRAME_CYCLES).unwrap();
+        recording.finish().unwrap();
+        let (_, samples) = take_files(&path);
+
+        // two frames' worth, the last one padded with silence
+        let length = 2 * FRAME_CYCLES * SAMPLE_RATE / CLOCK_SPEED;
+        assert_eq!(samples.len() as u64, length * 4);
+        assert_eq!(samples[..4], [2, 0, 0xFE, 0xFF]);
+        assert!(samples[800 * 4..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn empty_recordings_still_have_a_frame() {
+        let chunks = record("empty", &[]);
+        assert_eq!(delays(&chunks), [17]);
+        assert_eq!(chunks[1].1[..4], 1u32.to_be_bytes());
+    }
+}
@@ -0,0 +1,222 @@
+use std::{fs, io, path::Path};
+
+use crate::emulator::runtime::ppu::{FrameBuffer, HEIGHT, RGBA8888, WIDTH};
+
+// frames saved as png, for bug reports and for comparing the renderer's output against
+// known good images. there's no png or zlib crate around, so this writes the simplest png
+// there is: 8 bit rgba, no filtering and uncompressed deflate blocks. that's about 90kb
+// for a native size frame, which is fine for what it's used for.
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+// the most a stored deflate block can hold
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+pub fn save(frame: &FrameBuffer, scale: usize, path: impl AsRef<Path>) -> io::Result<()> {
+    fs::write(path, encode_frame(frame, scale))
+}
+
+// a frame as png, with every pixel blown up to `scale` x `scale`
+pub fn encode_frame(frame: &FrameBuffer, scale: usize) -> Vec<u8> {
+    let scale = scale.max(1);
+    encode(&frame[..], WIDTH, HEIGHT, scale)
+}
+
+pub fn encode(pixels: &[RGBA8888], width: usize, height: usize, scale: usize) -> Vec<u8> {
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header(width * scale, height * scale));
+    write_chunk(
+        &mut png,
+        b"IDAT",
+        &zlib_stored(&raw_image(pixels, width, scale)),
+    );
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub fn header(width: usize, height: usize) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // bit depth 8, colour type 6 (rgba), default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    header
+}
+
+// the image data before compression, with every pixel blown up to `scale` x `scale`
+pub fn raw_image(pixels: &[RGBA8888], width: usize, scale: usize) -> Vec<u8> {
+    let scaled_width = width * scale;
+
+    // every row starts with the filter type, 0 being none
+    let mut raw = Vec::with_capacity(pixels.len() / width * scale * (1 + scaled_width * 4));
+    for row in pixels.chunks_exact(width) {
+        let start = raw.len();
+        raw.push(0);
+        for pixel in row {
+            for _ in 0..scale {
+                raw.extend_from_slice(&pixel.to_be_bytes());
+            }
+        }
+        // the remaining copies of the row are the same bytes over again
+        for _ in 1..scale {
+            raw.extend_from_within(start..start + 1 + scaled_width * 4);
+        }
+    }
+    raw
+}
+
+pub fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    // the checksum covers the chunk type as well
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// wraps `data` in a zlib stream without compressing it
+pub fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len().div_ceil(MAX_STORED_BLOCK).max(1);
+    let mut stream = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    // deflate with a 32k window, no preset dictionary, lowest compression level
+    stream.extend_from_slice(&[0x78, 0x01]);
+
+    let mut chunks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let is_final = chunks.peek().is_none();
+        // block type 00 is stored, the lowest bit marks the last block
+        stream.push(is_final as u8);
+        let length = chunk.len() as u16;
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(chunk);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MODULO: u32 = 65521;
+
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is as many as can be summed up before b could overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MODULO;
+        b %= MODULO;
+    }
+    (b << 16) | a
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let crc = data.iter().fold(0xFFFFFFFFu32, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    });
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_the_reference_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        // long enough for the modulo to kick in more than once
+        assert_eq!(adler32(&[0xFF; 100_000]), 0x149A_302C);
+    }
+
+    #[test]
+    fn chunks_are_length_type_data_crc() {
+        let mut png = Vec::new();
+        write_chunk(&mut png, b"IEND", &[]);
+        assert_eq!(
+            png,
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+
+        let mut png = Vec::new();
+        write_chunk(&mut png, b"tEXt", b"abc");
+        assert_eq!(png[..4], 3u32.to_be_bytes());
+        assert_eq!(&png[4..11], b"tEXtabc");
+        assert_eq!(png[11..], crc32(b"tEXtabc").to_be_bytes());
+    }
+
+    #[test]
+    fn empty_input_still_gets_a_final_block() {
+        assert_eq!(
+            zlib_stored(&[]),
+            [0x78, 0x01, 1, 0, 0, 0xFF, 0xFF, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn long_input_is_split_into_stored_blocks() {
+        let data: Vec<u8> = (0..MAX_STORED_BLOCK + 10).map(|i| i as u8).collect();
+        let stream = zlib_stored(&data);
+        // the zlib header is a multiple of 31
+        assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0);
+
+        let first = &stream[2..];
+        assert_eq!(first[..5], [0, 0xFF, 0xFF, 0, 0]);
+        assert_eq!(first[5..5 + MAX_STORED_BLOCK], data[..MAX_STORED_BLOCK]);
+
+        let last = &first[5 + MAX_STORED_BLOCK..];
+        assert_eq!(last[..5], [1, 10, 0, !10, 0xFF]);
+        assert_eq!(last[5..15], data[MAX_STORED_BLOCK..]);
+        assert_eq!(last[15..], adler32(&data).to_be_bytes());
+    }
+
+    #[test]
+    fn pixels_are_scaled_up_row_by_row() {
+        let pixels = [0x11223344, 0x55667788];
+        let raw = raw_image(&pixels, 2, 2);
+        let row = [
+            0, 0x11, 0x22, 0x33, 0x44, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x55, 0x66,
+            0x77, 0x88,
+        ];
+        assert_eq!(raw, [row, row].concat());
+    }
+
+    #[test]
+    fn frames_start_with_the_signature_and_header() {
+        let png = encode_frame(&Box::new([0; WIDTH * HEIGHT]), 3);
+        assert_eq!(png[..8], SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..29], header(WIDTH * 3, HEIGHT * 3));
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+}
@@ -0,0 +1,156 @@
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// a lock-free triple buffer for handing data like frame buffers from the emulator thread
+// to a frontend. the writer and the reader each own one of the buffers, the third one
+// is shared and gets swapped with whichever side wants to hand over or pick up data.
+// neither side ever waits for the other, and the reader always gets the latest
+// complete value, skipping the ones it was too slow for.
+
+// set in `shared` when the shared buffer holds something the reader hasn't seen yet
+const FRESH: u8 = 0b100;
+const INDEX: u8 = 0b011;
+
+struct Shared<T> {
+    buffers: [UnsafeCell<T>; 3],
+    // index of the buffer that's currently owned by neither side, plus the `FRESH` bit
+    shared: AtomicU8,
+}
+
+// SAFETY: each buffer is only ever accessed by the side that currently owns its index,
+// and ownership only changes hands through the atomic swaps on `shared`
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+pub struct Writer<T> {
+    shared: Arc<Shared<T>>,
+    back: u8,
+}
+
+pub struct Reader<T> {
+    shared: Arc<Shared<T>>,
+    front: u8,
+}
+
+pub fn triple_buffer<T: Clone>(initial: T) -> (Writer<T>, Reader<T>) {
+    let shared = Arc::new(Shared {
+        buffers: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        shared: AtomicU8::new(1),
+    });
+
+    (
+        Writer {
+            shared: shared.clone(),
+            back: 0,
+        },
+        Reader { shared, front: 2 },
+    )
+}
+
+impl<T> Writer<T> {
+    // the buffer the writer is free to fill, it's not visible to the reader until `publish`
+    pub fn back_mut(&mut self) -> &mut T {
+        // SAFETY: `back` is owned by the writer, see `Shared`
+        unsafe { &mut *self.shared.buffers[self.back as usize].get() }
+    }
+
+    // hands the back buffer over to the reader
+    pub fn publish(&mut self) {
+        let previous = self.shared.shared.swap(self.back | FRESH, Ordering::AcqRel);
+        self.back = previous & INDEX;
+    }
+}
+
+impl<T> Reader<T> {
+    // whether something was published since the last call to `latest`
+    pub fn has_fresh(&self) -> bool {
+        self.shared.shared.load(Ordering::Acquire) & FRESH != 0
+    }
+
+    // the most recently published value
+    pub fn latest(&mut self) -> &T {
+        if self.has_fresh() {
+            let previous = self.shared.shared.swap(self.front, Ordering::AcqRel);
+            self.front = previous & INDEX;
+        }
+        // SAFETY: `front` is owned by the reader, see `Shared`
+        unsafe { &*self.shared.buffers[self.front as usize].get() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_starts_with_the_initial_value() {
+        let (_, mut reader) = triple_buffer(7);
+        assert!(!reader.has_fresh());
+        assert_eq!(*reader.latest(), 7);
+    }
+
+    #[test]
+    fn nothing_is_visible_until_published() {
+        let (mut writer, mut reader) = triple_buffer(0);
+        *writer.back_mut() = 1;
+        assert!(!reader.has_fresh());
+        assert_eq!(*reader.latest(), 0);
+
+        writer.publish();
+        assert!(reader.has_fresh());
+        assert_eq!(*reader.latest(), 1);
+        assert!(!reader.has_fresh());
+        // reading again without a new publish keeps the same value
+        assert_eq!(*reader.latest(), 1);
+    }
+
+    #[test]
+    fn reader_skips_to_the_latest_publish() {
+        let (mut writer, mut reader) = triple_buffer(0);
+        for value in 1..=5 {
+            *writer.back_mut() = value;
+            writer.publish();
+        }
+        assert_eq!(*reader.latest(), 5);
+    }
+
+    #[test]
+    fn writer_never_gets_the_buffer_the_reader_holds() {
+        let (mut writer, mut reader) = triple_buffer(0);
+        *writer.back_mut() = 1;
+        writer.publish();
+        assert_eq!(*reader.latest(), 1);
+
+        // the writer keeps going while the reader holds on to 1
+        for value in 2..=4 {
+            *writer.back_mut() = value;
+            writer.publish();
+            assert_ne!(writer.back, reader.front);
+        }
+        assert_eq!(*reader.latest(), 4);
+    }
+
+    #[test]
+    fn values_cross_threads() {
+        let (mut writer, mut reader) = triple_buffer(0u64);
+        let handle = std::thread::spawn(move || {
+            for value in 1..=10_000 {
+                *writer.back_mut() = value;
+                writer.publish();
+            }
+        });
+
+        let mut last = 0;
+        while last < 10_000 {
+            let value = *reader.latest();
+            // values only ever move forward, never torn or stale
+            assert!(value >= last);
+            last = value;
+        }
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::emulator::runtime::bus::io::apu::{SAMPLE_RATE, Sample};
+
+// the apu's output as a wav file: 16 bit stereo pcm at the rate the apu mixes at, which
+// is the one flavour of wav that everything plays. the sizes in the header aren't known
+// until the end, so it starts out with 0s that `finish` patches.
+
+const CHANNELS: u16 = 2;
+const BYTES_PER_SAMPLE: u32 = 2 * CHANNELS as u32;
+
+pub struct Wav {
+    file: BufWriter<File>,
+    samples: u32,
+}
+
+impl Wav {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&header(0))?;
+        Ok(Self { file, samples: 0 })
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn push(&mut self, samples: &[Sample]) -> io::Result<()> {
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flatten()
+            .flat_map(|side| side.to_le_bytes())
+            .collect();
+        self.samples += samples.len() as u32;
+        self.file.write_all(&bytes)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header(self.samples))?;
+        self.file.flush()
+    }
+}
+
+fn header(samples: u32) -> Vec<u8> {
+    let data_size = samples * BYTES_PER_SAMPLE;
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    // everything after this field: the rest of the header plus the samples
+    header.extend_from_slice(&(36 + data_size).to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    // uncompressed pcm
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&CHANNELS.to_le_bytes());
+    header.extend_from_slice(&(SAMPLE_RATE as u32).to_le_bytes());
+    header.extend_from_slice(&(SAMPLE_RATE as u32 * BYTES_PER_SAMPLE).to_le_bytes());
+    header.extend_from_slice(&(BYTES_PER_SAMPLE as u16).to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_size.to_le_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn sizes_are_patched_in_at_the_end() {
+        let path = std::env::temp_dir().join(format!("bamegoy-wav-{}.wav", std::process::id()));
+        let mut wav = Wav::create(&path).unwrap();
+        wav.push(&[[1, -1], [0x1234, 0]]).unwrap();
+        wav.push(&[[0, 2]]).unwrap();
+        wav.finish().unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(bytes.len(), 44 + 3 * 4);
+        assert_eq!(bytes[4..8], (36u32 + 12).to_le_bytes());
+        assert_eq!(bytes[24..28], 48_000u32.to_le_bytes());
+        assert_eq!(bytes[40..44], 12u32.to_le_bytes());
+        assert_eq!(
+            bytes[44..],
+            [1, 0, 0xFF, 0xFF, 0x34, 0x12, 0, 0, 0, 0, 2, 0]
+        );
+    }
+}
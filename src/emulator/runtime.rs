@@ -2,7 +2,9 @@ pub mod bus;
 pub mod cpu;
 pub mod disassemble;
 pub mod instruction;
+pub mod model;
 pub mod ppu;
+pub mod sgb;
 
 use crate::emulator::host::{EmulatorMessage, policy::Policy};
 use crate::emulator::runtime::{bus::Bus, cpu::CPU};
@@ -32,22 +34,50 @@ impl Runtime {
     }
 
     pub fn transition_to(&mut self, new_state: State, new_policy: Option<Policy>) {
+        if new_state == State::Paused {
+            self.show_paused_frame();
+        }
         self.state = new_state;
         self.policy = new_policy;
     }
 
+    // lets the frontend see a frame that's only partly drawn when pausing mid frame
+    fn show_paused_frame(&mut self) {
+        if let Some(ppu) = self.bus.ppu_mut() {
+            ppu.publish_partial_frame();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state == State::Paused
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    pub fn take_completed_frame(&mut self) -> Option<u64> {
+        self.bus.ppu_mut()?.take_completed_frame()
+    }
+
+    pub fn take_lcd_disabled_outside_vblank(&mut self) -> Option<u8> {
+        self.bus.ppu_mut()?.take_lcd_disabled_outside_vblank()
+    }
+
     pub fn handle_current_state(&mut self) -> Option<EmulatorMessage> {
         match self.state {
             State::Paused => None,
             State::Running => {
-                self.cpu.step(&mut self.bus);
-
-                if let Some(p) = &mut self.policy {
-                    if p(&self.cpu, &self.bus) {
-                        self.policy = None;
-                        self.state = State::Paused;
-                        return Some(EmulatorMessage::Paused);
-                    }
+                let cycles = self.cpu.step(&mut self.bus);
+                self.bus.tick(cycles);
+
+                if let Some(p) = &mut self.policy
+                    && p(&self.cpu, &self.bus)
+                {
+                    self.policy = None;
+                    self.state = State::Paused;
+                    self.show_paused_frame();
+                    return Some(EmulatorMessage::Paused);
                 }
 
                 None
@@ -1,65 +1,604 @@
+use std::any::Any;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use device::{Device, DeviceId};
 use error::BusError;
-use io::interrupts::InterruptKind;
+use io::dma::OamDma;
+use io::hdma::{self, Hdma, Transfer};
+use io::speed::SpeedSwitch;
+use observer::{Access, AccessKind, Observer};
+
+use crate::emulator::runtime::model::Model;
+use crate::emulator::runtime::ppu::oam_bug::Corruption;
+use crate::emulator::runtime::ppu::palette::colorization;
+use crate::emulator::runtime::ppu::{Mode, Ppu};
 
-mod error;
+pub mod device;
+pub mod error;
+pub mod hram;
 pub mod io;
+pub mod observer;
+pub mod wram;
+
+// marks an address in `device_map` that isn't owned by any device
+const UNMAPPED: u8 = 0;
+
+// t-cycles per second at normal speed
+pub const CLOCK_SPEED: u64 = 4_194_304;
+
+// t-cycles the cpu is stopped for while switching speeds
+const SPEED_SWITCH_CYCLES: u32 = 2050 * 4;
 
 pub struct Bus {
+    model: Model,
     rom: Box<[u8]>,
-    vram: Box<[u8]>,
-    serial: io::serial::Serial,
+    devices: Vec<Box<dyn Device>>,
+    // one entry per address, holding the index + 1 of the device that owns it.
+    // this keeps the lookup in `read_byte` / `write_byte` a single array access.
+    device_map: Box<[u8]>,
+    // kept around so ticking doesn't need to look them up
+    ppu: DeviceId,
+    timer: DeviceId,
+    apu: DeviceId,
+    hdma: DeviceId,
+    oam_dma: DeviceId,
+    speed_switch: DeviceId,
+    observers: Vec<Arc<dyn Observer>>,
+    // t-cycles since power on, advanced by `tick`
+    cycles: u64,
+    // the same, but counted at normal speed even while in double speed. this is how much
+    // time has passed for the emulated console, in units of `CLOCK_SPEED`.
+    elapsed: u64,
+    // address of the last opcode fetch, so observers know which instruction caused an access
+    instruction_pc: u16,
+    // t-cycles of the current instruction that were already ticked by its memory accesses
+    ticked_ahead: u32,
+    // t-cycles the cpu still has to wait for, e.g. because of a dma transfer. the other
+    // devices keep running in the meantime.
+    stall: u32,
+    // set by a speed switch through KEY1, kept here so ticking doesn't need to ask
+    is_double_speed: bool,
     // fixme: interrupts shouldn't need to be pub
     pub interrupts: io::interrupts::Interrupts,
 }
 
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Bus {
     pub fn new() -> Self {
-        Self {
+        Self::with_model(Model::default())
+    }
+
+    pub fn with_model(model: Model) -> Self {
+        let mut bus = Self {
+            model,
             rom: vec![0; 0x8000].into_boxed_slice(),
-            vram: vec![0; 0x4000].into_boxed_slice(),
-            serial: io::serial::Serial::default(),
+            devices: Vec::new(),
+            device_map: vec![UNMAPPED; 0x10000].into_boxed_slice(),
+            ppu: 0,
+            timer: 0,
+            apu: 0,
+            hdma: 0,
+            oam_dma: 0,
+            speed_switch: 0,
+            observers: Vec::new(),
+            cycles: 0,
+            elapsed: 0,
+            instruction_pc: 0,
+            ticked_ahead: 0,
+            stall: 0,
+            is_double_speed: false,
             interrupts: io::interrupts::Interrupts::default(),
+        };
+
+        bus.attach(0xFF00..=0xFF00, Box::new(io::joypad::Joypad::new(model)));
+        bus.attach(0xFF01..=0xFF02, Box::new(io::serial::Serial::default()));
+        bus.timer = bus.attach(0xFF04..=0xFF07, Box::new(io::timer::Timer::default()));
+        bus.apu = bus.attach(0xFF10..=0xFF3F, Box::new(io::apu::Apu::new(model)));
+
+        let wram = bus.attach(0xC000..=0xFDFF, Box::new(wram::Wram::new(model)));
+        bus.map(0xFF70..=0xFF70, wram);
+        bus.attach(0xFF80..=0xFFFE, Box::new(hram::Hram::default()));
+
+        bus.oam_dma = bus.attach(0xFF46..=0xFF46, Box::new(OamDma::default()));
+        bus.speed_switch = bus.attach(0xFF4D..=0xFF4D, Box::new(SpeedSwitch::new(model)));
+        bus.hdma = bus.attach(0xFF51..=0xFF55, Box::new(Hdma::new(model)));
+
+        let cgb = bus.attach(0xFF4C..=0xFF4C, Box::new(io::cgb::CgbRegisters::new(model)));
+        bus.map(0xFF72..=0xFF75, cgb);
+
+        let ppu = bus.attach(0x8000..=0x9FFF, Box::new(Ppu::new(model)));
+        bus.map(0xFE00..=0xFEFF, ppu);
+        bus.map(0xFF40..=0xFF45, ppu);
+        bus.map(0xFF47..=0xFF4B, ppu);
+        // cgb only, the ppu ignores them on dmg
+        bus.map(0xFF4F..=0xFF4F, ppu);
+        bus.map(0xFF68..=0xFF6C, ppu);
+        bus.ppu = ppu;
+
+        bus
+    }
+
+    // hands the given address range over to `device`. attaching over addresses that are
+    // already owned by another device remaps them, which is how tests swap in fakes.
+    pub fn attach(&mut self, range: RangeInclusive<u16>, device: Box<dyn Device>) -> DeviceId {
+        assert!(
+            self.devices.len() < u8::MAX as usize,
+            "device table is full"
+        );
+        self.devices.push(device);
+        let id = self.devices.len() - 1;
+        self.map(range, id);
+        id
+    }
+
+    // maps an additional address range to an already attached device, for hardware
+    // whose registers aren't contiguous
+    pub fn map(&mut self, range: RangeInclusive<u16>, id: DeviceId) {
+        assert!(id < self.devices.len(), "no device with id {}", id);
+        for addr in range {
+            self.device_map[addr as usize] = id as u8 + 1;
+        }
+    }
+
+    // returns the first attached device of type `T`
+    pub fn device<T: Device>(&self) -> Option<&T> {
+        self.devices
+            .iter()
+            .find_map(|d| (d.as_ref() as &dyn Any).downcast_ref::<T>())
+    }
+
+    pub fn device_mut<T: Device>(&mut self) -> Option<&mut T> {
+        self.devices
+            .iter_mut()
+            .find_map(|d| (d.as_mut() as &mut dyn Any).downcast_mut::<T>())
+    }
+
+    // the ppu through its cached id, which is cheap enough to call after every instruction
+    pub fn ppu(&self) -> Option<&Ppu> {
+        (self.devices[self.ppu].as_ref() as &dyn Any).downcast_ref::<Ppu>()
+    }
+
+    pub fn ppu_mut(&mut self) -> Option<&mut Ppu> {
+        (self.devices[self.ppu].as_mut() as &mut dyn Any).downcast_mut::<Ppu>()
+    }
+
+    fn hdma(&mut self) -> Option<&mut Hdma> {
+        (self.devices[self.hdma].as_mut() as &mut dyn Any).downcast_mut::<Hdma>()
+    }
+
+    fn oam_dma(&self) -> Option<&OamDma> {
+        (self.devices[self.oam_dma].as_ref() as &dyn Any).downcast_ref::<OamDma>()
+    }
+
+    fn oam_dma_mut(&mut self) -> Option<&mut OamDma> {
+        (self.devices[self.oam_dma].as_mut() as &mut dyn Any).downcast_mut::<OamDma>()
+    }
+
+    pub fn observe(&mut self, observer: Arc<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn elapsed(&self) -> u64 {
+        self.elapsed
+    }
+
+    // the bank currently mapped at `addr`, or 0 for regions that can't be switched
+    pub fn bank_of(&self, addr: u16) -> u16 {
+        let id = self.device_map[addr as usize];
+        if id != UNMAPPED {
+            return self.devices[id as usize - 1].bank_of(addr);
+        }
+
+        match addr {
+            0x4000..=0x7FFF => 1,
+            _ => 0,
         }
     }
 
-    pub fn from_cartridge_rom(cart: Vec<u8>) -> Result<Self, String> {
-        let mut bus = Self::new();
+    pub fn is_double_speed(&self) -> bool {
+        self.is_double_speed
+    }
+
+    // called by STOP. returns whether an armed speed switch was carried out, which is
+    // the only thing STOP is used for on cgb.
+    pub fn switch_speed(&mut self) -> bool {
+        let Some(is_double_speed) = (self.devices[self.speed_switch].as_mut() as &mut dyn Any)
+            .downcast_mut::<SpeedSwitch>()
+            .and_then(|key1| key1.switch())
+        else {
+            return false;
+        };
+        self.is_double_speed = is_double_speed;
+        self.stall += SPEED_SWITCH_CYCLES;
+        true
+    }
+
+    // STOP resets the divider, the same way a write to DIV does
+    pub fn reset_divider(&mut self) {
+        if let Err(e) = self.write(0xFF04, 0) {
+            eprintln!("{}", e);
+        }
+    }
+
+    // advances every attached device by the amount of t-cycles the cpu just spent, plus
+    // however long the cpu has to wait afterwards. memory accesses have already ticked
+    // their own m-cycle, so only the rest of the instruction is left.
+    pub fn tick(&mut self, cycles: u8) {
+        let cycles = (cycles as u32).saturating_sub(self.ticked_ahead);
+        self.ticked_ahead = 0;
+        self.tick_devices(cycles as u8);
+        while self.stall > 0 {
+            let cycles = self.stall.min(4) as u8;
+            self.stall -= cycles as u32;
+            self.tick_devices(cycles);
+        }
+    }
+
+    // every memory access takes an m-cycle, and the rest of the system has to be at the
+    // end of it for the access to see e.g. the right ppu mode or timer value
+    fn tick_access(&mut self) {
+        self.tick_devices(4);
+        self.ticked_ahead += 4;
+    }
+
+    fn tick_devices(&mut self, cycles: u8) {
+        self.cycles += cycles as u64;
+        self.elapsed += if self.is_double_speed {
+            cycles as u64 / 2
+        } else {
+            cycles as u64
+        };
+        let divider = self.divider();
+        for device in self.devices.iter_mut() {
+            let cycles = if self.is_double_speed && !device.runs_at_double_speed() {
+                cycles / 2
+            } else {
+                cycles
+            };
+            device.tick(cycles, &mut self.interrupts);
+        }
+        self.clock_frame_sequencer(divider);
+        self.copy_oam_dma_bytes();
+
+        if let Some(ppu) = self.ppu_mut()
+            && ppu.take_entered_hblank()
+            && self.hdma().is_some_and(|hdma| hdma.is_hblank_active())
+        {
+            self.copy_hdma_block();
+        }
+    }
+
+    fn divider(&self) -> u16 {
+        (self.devices[self.timer].as_ref() as &dyn Any)
+            .downcast_ref::<io::timer::Timer>()
+            .map_or(0, |timer| timer.divider())
+    }
+
+    // the apu's frame sequencer steps whenever bit 4 of DIV falls, bit 5 in double speed
+    // so it keeps running at 512 hz. resetting DIV can cause that too.
+    fn clock_frame_sequencer(&mut self, previous_divider: u16) {
+        let bit = if self.is_double_speed { 13 } else { 12 };
+        if previous_divider & (1 << bit) == 0 || self.divider() & (1 << bit) != 0 {
+            return;
+        }
+        if let Some(apu) =
+            (self.devices[self.apu].as_mut() as &mut dyn Any).downcast_mut::<io::apu::Apu>()
+        {
+            apu.clock_frame_sequencer();
+        }
+    }
+
+    // copies whatever bytes of an oam dma are due by now
+    fn copy_oam_dma_bytes(&mut self) {
+        while let Some((source, offset)) = self.oam_dma_mut().and_then(|dma| dma.next_byte()) {
+            // everything from 0xE000 up reads echo ram
+            let source = if source >= 0xE000 {
+                source - 0x2000
+            } else {
+                source
+            };
+            let content = self.read(source).unwrap_or(0xFF);
+            if let Some(ppu) = self.ppu_mut()
+                && let Err(e) = ppu.poke(0xFE00 + offset, content)
+            {
+                eprintln!("{}", e);
+            }
+        }
+    }
+
+    // copies the next 16 bytes of a vram dma transfer and holds up the cpu for it
+    fn copy_hdma_block(&mut self) {
+        let Some((source, destination)) = self.hdma().map(|hdma| hdma.next_block()) else {
+            return;
+        };
+        for offset in 0..hdma::BLOCK_SIZE {
+            // vram can't be a source, it would be copying onto itself. that and unmapped
+            // sources read 0xFF here
+            let source = source.wrapping_add(offset);
+            let content = if (0x8000..0xA000).contains(&source) {
+                0xFF
+            } else {
+                self.read(source).unwrap_or(0xFF)
+            };
+            if let Err(e) = self.write(destination + offset, content) {
+                eprintln!("{}", e);
+            }
+        }
+        // 8 m-cycles per block, in double speed the cpu gets through twice as many
+        self.stall += if self.is_double_speed { 64 } else { 32 };
+    }
+
+    // carries out whatever a write to HDMA5 asked for
+    fn start_hdma(&mut self) {
+        let Some(hdma) = self.hdma() else {
+            return;
+        };
+        let blocks = hdma.blocks();
+        match hdma.take_transfer() {
+            None => (),
+            Some(Transfer::General) => {
+                for _ in 0..blocks {
+                    self.copy_hdma_block();
+                }
+            }
+            // started during hblank or with the lcd off, the first block goes right away
+            Some(Transfer::HBlank) => {
+                let is_in_hblank = self
+                    .ppu()
+                    .is_some_and(|ppu| !ppu.is_lcd_enabled() || ppu.mode() == Mode::HBlank);
+                if is_in_hblank {
+                    self.copy_hdma_block();
+                }
+            }
+        }
+    }
+
+    pub fn from_cartridge_rom(cart: Vec<u8>, model: Model) -> Result<Self, String> {
+        let mut bus = Self::with_model(model);
         if cart.len() > bus.rom.len() {
             return Err("Cartridge rom too big!".to_string());
         }
         bus.rom[..cart.len()].copy_from_slice(&cart);
+
+        // the part of the cgb boot rom that picks colours for dmg games
+        if model == Model::CgbCompat
+            && let Some(ppu) = bus.ppu_mut()
+        {
+            ppu.set_palettes(colorization::palettes_for(&cart));
+        }
         Ok(bus)
     }
 
-    pub fn read_byte(&self, addr: u16) -> Result<u8, BusError> {
+    // whether the ppu currently keeps the cpu away from `addr`. a running oam dma keeps
+    // it out of oam as well.
+    fn is_locked_by_ppu(&self, addr: u16) -> bool {
+        match addr {
+            0xFE00..=0xFEFF if self.oam_dma().is_some_and(|dma| dma.is_active()) => true,
+            0x8000..=0x9FFF | 0xFE00..=0xFEFF => self.ppu().is_some_and(|ppu| ppu.is_locked(addr)),
+            _ => false,
+        }
+    }
+
+    // hands whatever the joypad received over to the sgb, which lives with the ppu
+    fn forward_sgb_packets(&mut self) {
+        while let Some(packet) = self
+            .device_mut::<io::joypad::Joypad>()
+            .and_then(|joypad| joypad.take_sgb_packet())
+        {
+            if let Some(ppu) = self.ppu_mut() {
+                ppu.receive_sgb_packet(&packet);
+            }
+        }
+    }
+
+    // called for cpu accesses and for register pairs going through the 16 bit inc/dec
+    // unit, which scramble oam on dmg when they point at 0xFE00-0xFEFF at the wrong time
+    pub fn trigger_oam_bug(&mut self, addr: u16, kind: Corruption) {
+        if !(0xFE00..=0xFEFF).contains(&addr) {
+            return;
+        }
+        if let Some(ppu) = self.ppu_mut() {
+            ppu.corrupt_oam(kind);
+        }
+    }
+
+    // cpu accesses to vram and oam are blocked while the ppu is using them, reads get
+    // 0xFF and writes are dropped
+    pub fn read_byte(&mut self, addr: u16) -> Result<u8, BusError> {
+        self.read_byte_as(addr, Corruption::Read)
+    }
+
+    // a read through a register pair that the inc/dec unit steps in the same m-cycle,
+    // like LD A, [HL+] or POP. that's a different kind of oam corruption than a read.
+    pub fn read_byte_during_inc_dec(&mut self, addr: u16) -> Result<u8, BusError> {
+        self.read_byte_as(addr, Corruption::ReadDuringIncDec)
+    }
+
+    fn read_byte_as(&mut self, addr: u16, corruption: Corruption) -> Result<u8, BusError> {
+        self.tick_access();
+        self.trigger_oam_bug(addr, corruption);
+        let value = if self.is_locked_by_ppu(addr) {
+            0xFF
+        } else {
+            self.read(addr)?
+        };
+        if !self.observers.is_empty() {
+            self.notify(AccessKind::Read, addr, value);
+        }
+        Ok(value)
+    }
+
+    pub fn write_byte(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        self.tick_access();
+        self.trigger_oam_bug(addr, Corruption::Write);
+        if !self.is_locked_by_ppu(addr) {
+            self.write(addr, content)?;
+        }
+        if addr == 0xFF00 && self.model == Model::Sgb {
+            self.forward_sgb_packets();
+        }
+        if !self.observers.is_empty() {
+            self.notify(AccessKind::Write, addr, content);
+        }
+        Ok(())
+    }
+
+    // reads the opcode at `addr` and remembers it as the instruction being executed
+    pub fn fetch_byte(&mut self, addr: u16) -> Result<u8, BusError> {
+        self.tick_access();
+        self.instruction_pc = addr;
+        let value = if self.is_locked_by_ppu(addr) {
+            0xFF
+        } else {
+            self.read(addr)?
+        };
+        if !self.observers.is_empty() {
+            self.notify(AccessKind::Fetch, addr, value);
+        }
+        Ok(value)
+    }
+
+    fn notify(&self, kind: AccessKind, addr: u16, value: u8) {
+        let access = Access {
+            kind,
+            addr,
+            value,
+            pc: self.instruction_pc,
+            bank: self.bank_of(addr),
+            cycle: self.cycles,
+        };
+        for observer in &self.observers {
+            observer.on_access(&access);
+        }
+    }
+
+    fn read(&self, addr: u16) -> Result<u8, BusError> {
+        let id = self.device_map[addr as usize];
+        if id != UNMAPPED {
+            return self.devices[id as usize - 1].read(addr);
+        }
+
         match addr {
             0x0..=0x7FFF => Self::mem_read(&self.rom, addr),
-            0x8000..=0x9FFF => Self::mem_read(&self.vram, addr - 0x8000),
-            0xFF00..0xFF80 => match addr {
-                0xFF01 | 0xFF02 => self.serial.read(addr),
-                0xFF0F | 0xFFFF => self.interrupts.read(addr),
-                _ => Err(BusError::Unimplemented(addr)),
-            },
+            0xFF0F | 0xFFFF => self.interrupts.read(addr),
+            // io addresses without a register behind them read like open bus, and so
+            // does cartridge ram on the cartridges that have none
+            0xA000..0xC000 | 0xFF00..0xFF80 => Ok(0xFF),
             _ => Err(BusError::OutOfBounds(addr)),
         }
     }
 
-    pub fn write_byte(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+    fn write(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        let id = self.device_map[addr as usize];
+        if id != UNMAPPED {
+            // resetting DIV can step the frame sequencer
+            let divider = (addr == 0xFF04).then(|| self.divider());
+            self.devices[id as usize - 1].write(addr, content, &mut self.interrupts)?;
+            if let Some(divider) = divider {
+                self.clock_frame_sequencer(divider);
+            }
+            // and HDMA5 can start a vram dma
+            if addr == 0xFF55 {
+                self.start_hdma();
+            }
+            return Ok(());
+        }
+
         match addr {
             0x0..0x8000 => Self::mem_write(&mut self.rom, addr, content),
-            0xFF00..0xFF80 => match addr {
-                0xFF01 | 0xFF02 => {
-                    let serial_int = &mut self.interrupts.get_mut(InterruptKind::Serial);
-                    self.serial.write(addr, content, serial_int)
-                }
-                0xFF0F | 0xFFFF => self.interrupts.write(addr, content),
-                _ => Err(BusError::Unimplemented(addr)),
-            },
+            0xFF0F | 0xFFFF => self.interrupts.write(addr, content),
+            // and writes to them go nowhere, games do write to some of them
+            0xA000..0xC000 | 0xFF00..0xFF80 => Ok(()),
+            _ => Err(BusError::OutOfBounds(addr)),
+        }
+    }
+
+    // side effect free counterparts to `read_byte` / `write_byte` for debugging tools.
+    // they don't notify observers, don't trigger device side effects and ignore any
+    // access restrictions, so they're safe to call while the emulator is paused.
+    pub fn peek(&self, addr: u16) -> Result<u8, BusError> {
+        self.peek_bank(self.bank_of(addr), addr)
+    }
+
+    pub fn poke(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        self.poke_bank(self.bank_of(addr), addr, content)
+    }
+
+    pub fn peek_word(&self, addr: u16) -> Result<u16, BusError> {
+        let lo = self.peek(addr)?;
+        let hi = self.peek(addr.wrapping_add(1))?;
+
+        Ok(((hi as u16) << 8) | lo as u16)
+    }
+
+    // like `peek`, but reads from `bank` regardless of what's currently switched in
+    pub fn peek_bank(&self, bank: u16, addr: u16) -> Result<u8, BusError> {
+        let id = self.device_map[addr as usize];
+        if id != UNMAPPED {
+            return self.devices[id as usize - 1].peek_bank(bank, addr);
+        }
+
+        if addr == 0xFF0F || addr == 0xFFFF {
+            return self.interrupts.read(addr);
+        }
+
+        let offset = self.banked_offset(bank, addr)?;
+        self.rom
+            .get(offset)
+            .copied()
+            .ok_or(BusError::NoSuchBank(bank, addr))
+    }
+
+    pub fn poke_bank(&mut self, bank: u16, addr: u16, content: u8) -> Result<(), BusError> {
+        let id = self.device_map[addr as usize];
+        if id != UNMAPPED {
+            return self.devices[id as usize - 1].poke_bank(bank, addr, content);
+        }
+
+        if addr == 0xFF0F || addr == 0xFFFF {
+            return self.interrupts.write(addr, content);
+        }
+
+        let offset = self.banked_offset(bank, addr)?;
+        let byte = self
+            .rom
+            .get_mut(offset)
+            .ok_or(BusError::NoSuchBank(bank, addr))?;
+        *byte = content;
+        Ok(())
+    }
+
+    // translates an address into an index into its backing memory for the given bank
+    fn banked_offset(&self, bank: u16, addr: u16) -> Result<usize, BusError> {
+        let bank = bank as usize;
+        match addr {
+            0x0..=0x3FFF if bank == 0 => Ok(addr as usize),
+            0x4000..=0x7FFF => Ok(bank * 0x4000 + (addr - 0x4000) as usize),
+            0x0..=0x3FFF => Err(BusError::NoSuchBank(bank as u16, addr)),
+            0xFF00..0xFF80 => Err(BusError::Unimplemented(addr)),
             _ => Err(BusError::OutOfBounds(addr)),
         }
     }
 
-    pub fn read_word(&self, addr: u16) -> Result<u16, BusError> {
+    pub fn read_word(&mut self, addr: u16) -> Result<u16, BusError> {
         let lo = self.read_byte(addr)?;
         let hi = self.read_byte(addr + 1)?;
 
@@ -91,24 +630,296 @@ impl Bus {
         Ok(())
     }
 
+    // one m-cycle to decrement SP, which can corrupt oam on its own, then one write per
+    // byte, high byte first
     pub fn push_word(&mut self, sp: &mut u16, content: u16) -> Result<(), BusError> {
-        *sp -= 2;
+        self.tick_access();
+        self.trigger_oam_bug(*sp, Corruption::Write);
+        *sp = sp.wrapping_sub(1);
+        self.write_byte(*sp, (content >> 8) as u8)?;
+        *sp = sp.wrapping_sub(1);
+        self.write_byte(*sp, content as u8)
+    }
+
+    // the low byte is read while SP is incremented, the high byte with a plain read
+    pub fn pop_word(&mut self, sp: &mut u16) -> Result<u16, BusError> {
+        let lo = self.read_byte_during_inc_dec(*sp)?;
+        *sp = sp.wrapping_add(1);
+        let hi = self.read_byte(*sp)?;
+        *sp = sp.wrapping_add(1);
 
-        if (*sp as usize) + 1 >= self.rom.len() {
-            return Err(BusError::OutOfBounds(*sp));
+        Ok(((hi as u16) << 8) | lo as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::runtime::cpu::CPU;
+    use crate::emulator::runtime::ppu::oam_bug;
+
+    fn bus_with_code(code: &[u8]) -> Bus {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x100 + code.len()].copy_from_slice(code);
+        Bus::from_cartridge_rom(rom, Model::default()).unwrap()
+    }
+
+    // runs the rest of the system up to the given t-cycle, counting from power on
+    fn tick_until(bus: &mut Bus, cycle: u64) {
+        bus.tick(0);
+        while bus.cycles() < cycle {
+            bus.tick(4);
         }
-        self.write_word(*sp, content)
     }
 
-    pub fn pop_word(&mut self, sp: &mut u16) -> Result<u16, BusError> {
-        if (*sp as usize) + 1 >= self.rom.len() {
-            return Err(BusError::OutOfBounds(*sp));
+    // runs one instruction on a dmg, starting 16 dots into the first oam scan with the
+    // given registers pointing into oam, and returns oam before and after
+    fn oam_around(code: &[u8], h: u8, l: u8, sp: u16) -> (Vec<u8>, Vec<u8>) {
+        let mut bus = bus_with_code(code);
+        for offset in 0..0xA0 {
+            bus.poke(0xFE00 + offset, offset as u8).unwrap();
+        }
+        let before = (0..0xA0).map(|offset| bus.peek(0xFE00 + offset).unwrap());
+        let before = before.collect();
+        tick_until(&mut bus, 16);
+
+        let mut cpu = CPU::new(false);
+        cpu.h = h;
+        cpu.l = l;
+        cpu.sp = sp;
+        let cycles = cpu.step(&mut bus);
+        bus.tick(cycles);
+        let after: Vec<u8> = (0..0xA0)
+            .map(|offset| bus.peek(0xFE00 + offset).unwrap())
+            .collect();
+        // nothing to compare if the instruction missed the scan
+        assert_ne!(before, after);
+        (before, after)
+    }
+
+    // what `sequence` does to `oam`, each one on the row the ppu is on at that dot
+    fn corrupted(mut oam: Vec<u8>, sequence: &[(u16, Corruption)]) -> Vec<u8> {
+        for &(dot, kind) in sequence {
+            oam_bug::corrupt(&mut oam, dot as usize / 4, kind);
+        }
+        oam
+    }
+
+    #[test]
+    fn accesses_see_the_state_at_their_own_m_cycle() {
+        // LDH A, (LY) reads in its third m-cycle, 12 dots after the instruction started
+        let mut bus = bus_with_code(&[0xF0, 0x44]);
+        bus.tick(200);
+        bus.tick(248);
+        let mut cpu = CPU::new(false);
+        let cycles = cpu.step(&mut bus);
+        bus.tick(cycles);
+
+        assert_eq!(cpu.a, 1);
+        assert_eq!(bus.cycles(), 448 + 12);
+    }
+
+    #[test]
+    fn general_hdma_copies_everything_and_holds_up_the_cpu() {
+        let mut bus = Bus::from_cartridge_rom(vec![0; 0x8000], Model::Cgb).unwrap();
+        for offset in 0..0x20 {
+            bus.write_byte(0xC000 + offset, offset as u8).unwrap();
         }
+        for (addr, content) in [
+            (0xFF51, 0xC0),
+            (0xFF52, 0x00),
+            (0xFF53, 0x00),
+            (0xFF54, 0x10),
+        ] {
+            bus.write_byte(addr, content).unwrap();
+        }
+        bus.write_byte(0xFF55, 0x01).unwrap();
+        bus.tick(0);
+
+        for offset in 0..0x20 {
+            assert_eq!(bus.peek(0x8010 + offset).unwrap(), offset as u8);
+        }
+        assert_eq!(bus.peek(0xFF55).unwrap(), 0xFF);
+        // 8 m-cycles per block on top of the accesses themselves
+        assert_eq!(bus.cycles(), (0x20 + 5) * 4 + 2 * 32);
+    }
+
+    #[test]
+    fn hdma_out_of_vram_copies_0xff() {
+        let mut bus = Bus::from_cartridge_rom(vec![0; 0x8000], Model::Cgb).unwrap();
+        bus.poke(0x8000, 0x42).unwrap();
+        for (addr, content) in [
+            (0xFF51, 0x80),
+            (0xFF52, 0x00),
+            (0xFF53, 0x00),
+            (0xFF54, 0x10),
+        ] {
+            bus.write_byte(addr, content).unwrap();
+        }
+        bus.write_byte(0xFF55, 0x00).unwrap();
+        bus.tick(0);
+
+        assert_eq!(bus.peek(0x8010).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn speed_switches_go_through_key1() {
+        let mut bus = Bus::from_cartridge_rom(vec![0; 0x8000], Model::Cgb).unwrap();
+        assert!(!bus.switch_speed());
+
+        bus.write_byte(0xFF4D, 0x01).unwrap();
+        assert!(bus.switch_speed());
+        assert!(bus.is_double_speed());
+        assert_eq!(bus.peek(0xFF4D).unwrap(), 0xFE);
+    }
 
-        let content = self.read_word(*sp)?;
+    #[test]
+    fn vram_and_oam_lock_on_the_mode_of_the_access_itself() {
+        let mut bus = bus_with_code(&[]);
+        bus.poke(0x8000, 0x42).unwrap();
+        bus.poke(0xFE00, 0x24).unwrap();
 
-        *sp += 2;
+        // oam scan runs for the first 80 dots of a line, drawing for the next 172
+        tick_until(&mut bus, 72);
+        assert_eq!(bus.read_byte(0x8000).unwrap(), 0x42);
+        assert_eq!(bus.read_byte(0xFE00).unwrap(), 0xFF);
+        tick_until(&mut bus, 76);
+        assert_eq!(bus.read_byte(0x8000).unwrap(), 0xFF);
+
+        tick_until(&mut bus, 244);
+        assert_eq!(bus.read_byte(0x8000).unwrap(), 0xFF);
+        assert_eq!(bus.read_byte(0x8000).unwrap(), 0x42);
+        assert_eq!(bus.read_byte(0xFE00).unwrap(), 0x24);
+
+        // writes are dropped the same way
+        tick_until(&mut bus, 456 + 76);
+        bus.write_byte(0x8000, 0x99).unwrap();
+        bus.write_byte(0xFE00, 0x99).unwrap();
+        assert_eq!(bus.peek(0x8000).unwrap(), 0x42);
+        assert_eq!(bus.peek(0xFE00).unwrap(), 0x24);
+    }
+
+    #[test]
+    fn ld_a_hl_increment_corrupts_oam_once() {
+        // LD A, [HL+] reads in its second m-cycle
+        let (before, after) = oam_around(&[0x2A], 0xFE, 0x10, 0xFFFE);
+        assert_eq!(
+            after,
+            corrupted(before, &[(24, Corruption::ReadDuringIncDec)])
+        );
+    }
+
+    #[test]
+    fn inc_hl_corrupts_oam_only_during_the_scan() {
+        // INC HL goes through the inc/dec unit without touching memory
+        let (before, after) = oam_around(&[0x23], 0xFE, 0x10, 0xFFFE);
+        assert_eq!(after, corrupted(before, &[(20, Corruption::Write)]));
+
+        // the same thing once drawing has started
+        let mut bus = bus_with_code(&[0x23]);
+        for offset in 0..0xA0 {
+            bus.poke(0xFE00 + offset, offset as u8).unwrap();
+        }
+        tick_until(&mut bus, 96);
+        let before: Vec<u8> = (0..0xA0)
+            .map(|offset| bus.peek(0xFE00 + offset).unwrap())
+            .collect();
+        let mut cpu = CPU::new(false);
+        cpu.h = 0xFE;
+        cpu.l = 0x10;
+        let cycles = cpu.step(&mut bus);
+        bus.tick(cycles);
+        let after: Vec<u8> = (0..0xA0)
+            .map(|offset| bus.peek(0xFE00 + offset).unwrap())
+            .collect();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn push_corrupts_oam_once_per_m_cycle() {
+        // PUSH BC: decrementing SP, then writing both bytes
+        let (before, after) = oam_around(&[0xC5], 0, 0, 0xFE20);
+        let sequence = [
+            (24, Corruption::Write),
+            (28, Corruption::Write),
+            (32, Corruption::Write),
+        ];
+        assert_eq!(after, corrupted(before, &sequence));
+    }
+
+    #[test]
+    fn pop_corrupts_oam_once_per_m_cycle() {
+        // POP BC: reading the low byte while incrementing SP, then the high byte
+        let (before, after) = oam_around(&[0xC1], 0, 0, 0xFE20);
+        let sequence = [(24, Corruption::ReadDuringIncDec), (28, Corruption::Read)];
+        assert_eq!(after, corrupted(before, &sequence));
+    }
+
+    #[test]
+    fn hl_can_walk_through_oam_and_the_unusable_area() {
+        // LD HL, 0xFE00; LD A, 0x42; LD B, 0; loop: LD (HL+), A; DEC B; JR NZ, loop
+        let code = [
+            0x21, 0x00, 0xFE, 0x3E, 0x42, 0x06, 0x00, 0x22, 0x05, 0x20, 0xFC,
+        ];
+        let mut bus = bus_with_code(&code);
+        let mut cpu = CPU::new(false);
+        while cpu.pc != 0x100 + code.len() as u16 {
+            let cycles = cpu.step(&mut bus);
+            bus.tick(cycles);
+        }
+        assert_eq!((cpu.h, cpu.l), (0xFF, 0x00));
+
+        // reads 0 and ignores writes, unless it's locked along with oam
+        bus.write_byte(0xFEA0, 0x42).unwrap();
+        bus.write_byte(0xFF40, 0x00).unwrap();
+        assert_eq!(bus.read_byte(0xFEA0).unwrap(), 0x00);
+        assert_eq!(bus.read_byte(0xFEFF).unwrap(), 0x00);
+        bus.write_byte(0xFF40, 0x91).unwrap();
+        assert_eq!(bus.read_byte(0xFEA0).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn missing_io_registers_and_cartridge_ram_read_like_open_bus() {
+        let mut bus = bus_with_code(&[]);
+        for addr in [0xA000, 0xBFFF, 0xFF03, 0xFF7F] {
+            bus.write_byte(addr, 0x42).unwrap();
+            assert_eq!(bus.read_byte(addr).unwrap(), 0xFF);
+        }
+    }
+
+    #[test]
+    fn oam_dma_copies_a_byte_per_m_cycle_and_locks_oam() {
+        let mut bus = bus_with_code(&[]);
+        bus.write_byte(0xFF40, 0x00).unwrap();
+        for offset in 0..0xA0 {
+            bus.write_byte(0xC100 + offset, offset as u8 + 1).unwrap();
+        }
+        bus.write_byte(0xFF46, 0xC1).unwrap();
+        let started = bus.cycles();
+        assert_eq!(bus.read_byte(0xFF46).unwrap(), 0xC1);
+
+        // the cpu is stuck in hram in the meantime, oam reads 0xFF
+        bus.write_byte(0xFF80, 0x42).unwrap();
+        assert_eq!(bus.read_byte(0xFF80).unwrap(), 0x42);
+        assert_eq!(bus.read_byte(0xFE00).unwrap(), 0xFF);
+
+        // an m-cycle to start, then one per byte. the read itself takes the 160th.
+        tick_until(&mut bus, started + 159 * 4);
+        assert_eq!(bus.read_byte(0xFE9F).unwrap(), 0xFF);
+        assert_eq!(bus.peek(0xFE9E).unwrap(), 0x9F);
+        assert_eq!(bus.read_byte(0xFE00).unwrap(), 0x01);
+        assert_eq!(bus.read_byte(0xFE9F).unwrap(), 0xA0);
+    }
 
-        Ok(content)
+    #[test]
+    fn pushed_words_pop_back_out() {
+        let mut bus = bus_with_code(&[]);
+        let mut sp = 0xD000;
+        bus.push_word(&mut sp, 0x1234).unwrap();
+        assert_eq!(sp, 0xCFFE);
+        // little endian, like everything else
+        assert_eq!(bus.peek_word(0xCFFE).unwrap(), 0x1234);
+        assert_eq!(bus.pop_word(&mut sp).unwrap(), 0x1234);
+        assert_eq!(sp, 0xD000);
     }
 }
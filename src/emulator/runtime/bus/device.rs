@@ -0,0 +1,66 @@
+use std::any::Any;
+
+use crate::emulator::runtime::bus::error::BusError;
+use crate::emulator::runtime::bus::io::interrupts::Interrupts;
+
+// index into the bus' device table, handed out by `Bus::attach`
+pub type DeviceId = usize;
+
+// anything that lives behind a range of addresses on the bus: io registers, the ppu,
+// cartridge mappers, ... the bus only knows which device owns an address and forwards
+// the access, so hardware can be added (or swapped out for a fake) without touching it.
+//
+// interrupts are not a device themselves, because pretty much every device needs to
+// request one. they get handed to the methods that can cause side effects instead.
+pub trait Device: Any + Send {
+    fn read(&self, addr: u16) -> Result<u8, BusError>;
+
+    fn write(
+        &mut self,
+        addr: u16,
+        content: u8,
+        interrupts: &mut Interrupts,
+    ) -> Result<(), BusError>;
+
+    // reads a register the way a debugger would: without any of the side effects a
+    // cpu read may have. devices whose reads are side effect free can rely on `read`.
+    fn peek(&self, addr: u16) -> Result<u8, BusError> {
+        self.read(addr)
+    }
+
+    // writes a register without triggering anything, e.g. a memory editor setting a
+    // value. interrupts requested by a plain `write` are thrown away by default.
+    fn poke(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        self.write(addr, content, &mut Interrupts::default())
+    }
+
+    // the bank currently switched in at `addr`, for devices that have banked memory
+    fn bank_of(&self, _addr: u16) -> u16 {
+        0
+    }
+
+    // `peek` / `poke` into a bank that isn't necessarily the one switched in right now
+    fn peek_bank(&self, bank: u16, addr: u16) -> Result<u8, BusError> {
+        if bank != self.bank_of(addr) {
+            return Err(BusError::NoSuchBank(bank, addr));
+        }
+        self.peek(addr)
+    }
+
+    fn poke_bank(&mut self, bank: u16, addr: u16, content: u8) -> Result<(), BusError> {
+        if bank != self.bank_of(addr) {
+            return Err(BusError::NoSuchBank(bank, addr));
+        }
+        self.poke(addr, content)
+    }
+
+    // advances the device by the given amount of t-cycles. most registers are purely
+    // passive, so this does nothing unless a device overrides it.
+    fn tick(&mut self, _cycles: u8, _interrupts: &mut Interrupts) {}
+
+    // whether the device speeds up along with the cpu in cgb double speed mode. ones that
+    // don't, like the ppu, only get half as many cycles ticked while it's on.
+    fn runs_at_double_speed(&self) -> bool {
+        true
+    }
+}
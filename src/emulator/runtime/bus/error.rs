@@ -2,6 +2,7 @@
 pub enum BusError {
     OutOfBounds(u16),
     Unimplemented(u16),
+    NoSuchBank(u16, u16),
 }
 
 impl std::fmt::Display for BusError {
@@ -13,6 +14,9 @@ impl std::fmt::Display for BusError {
             BusError::Unimplemented(addr) => {
                 write!(f, "reading from io address {} is not supported yet", addr)
             }
+            BusError::NoSuchBank(bank, addr) => {
+                write!(f, "Address {:04X} has no bank {}", addr, bank)
+            }
         }
     }
 }
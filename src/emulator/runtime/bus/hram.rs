@@ -0,0 +1,37 @@
+use crate::emulator::runtime::bus::device::Device;
+use crate::emulator::runtime::bus::error::BusError;
+use crate::emulator::runtime::bus::io::interrupts::Interrupts;
+
+// high ram at 0xFF80-0xFFFE. the stack lives here after boot, and it's the only memory
+// the cpu can reach while an oam dma is running, so that's where dma routines go.
+pub struct Hram {
+    bytes: [u8; 0x7F],
+}
+
+impl Default for Hram {
+    fn default() -> Self {
+        Self { bytes: [0; 0x7F] }
+    }
+}
+
+impl Device for Hram {
+    fn read(&self, addr: u16) -> Result<u8, BusError> {
+        match addr {
+            0xFF80..=0xFFFE => Ok(self.bytes[(addr - 0xFF80) as usize]),
+            _ => Err(BusError::Unimplemented(addr)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        addr: u16,
+        content: u8,
+        _interrupts: &mut Interrupts,
+    ) -> Result<(), BusError> {
+        match addr {
+            0xFF80..=0xFFFE => self.bytes[(addr - 0xFF80) as usize] = content,
+            _ => return Err(BusError::Unimplemented(addr)),
+        }
+        Ok(())
+    }
+}
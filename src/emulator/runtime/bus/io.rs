@@ -1,2 +1,9 @@
+pub mod apu;
+pub mod cgb;
+pub mod dma;
+pub mod hdma;
 pub mod interrupts;
+pub mod joypad;
 pub mod serial;
+pub mod speed;
+pub mod timer;
@@ -0,0 +1,494 @@
+use std::sync::mpsc::SyncSender;
+
+use crate::emulator::runtime::bus::CLOCK_SPEED;
+use crate::emulator::runtime::bus::device::Device;
+use crate::emulator::runtime::bus::error::BusError;
+use crate::emulator::runtime::model::Model;
+
+use super::interrupts::Interrupts;
+
+pub mod channel;
+pub mod noise;
+pub mod square;
+pub mod wave;
+
+// the audio processing unit mixes four channels: two square waves, one of them with a
+// frequency sweep, a wave channel playing back samples from wave ram and a noise channel.
+// the channels run off the regular clock, while lengths, envelopes and the sweep are
+// clocked by the frame sequencer. that one isn't a timer of its own, it's driven by the
+// divider, so the bus calls `clock_frame_sequencer` whenever the right bit of DIV falls.
+
+// what a sample is played back at, samples are taken from the mixer at this rate
+pub const SAMPLE_RATE: u64 = 48_000;
+
+// samples are handed out in batches of this many, about a frame's worth
+const SAMPLES_PER_BATCH: usize = 800;
+
+pub type Sample = [i16; 2];
+
+// bits that always read as 1, for NR10 to NR52
+const READ_MASKS: [u8; 0x17] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // unused, NR21-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // unused, NR41-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+];
+
+const NR50: u16 = 0xFF24;
+const NR51: u16 = 0xFF25;
+const NR52: u16 = 0xFF26;
+
+pub struct Apu {
+    model: Model,
+    is_powered: bool,
+    // what was last written to NR10-NR51, for reading them back
+    registers: [u8; 0x17],
+    // the step the frame sequencer runs next, out of 8
+    frame_step: u8,
+
+    square1: square::Square,
+    square2: square::Square,
+    wave: wave::Wave,
+    noise: noise::Noise,
+
+    // counts up by `SAMPLE_RATE` every t-cycle, a sample is due every `CLOCK_SPEED`
+    sample_clock: u64,
+    samples: Vec<Sample>,
+    // where samples go, nothing is mixed unless someone's listening
+    sample_sender: Option<SyncSender<Vec<Sample>>>,
+    // every sample since the host last took them, while a recording is running
+    recorded: Option<Vec<Sample>>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new(Model::default())
+    }
+}
+
+impl Apu {
+    pub fn new(model: Model) -> Self {
+        let mut apu = Self {
+            model,
+            is_powered: true,
+            registers: [0; 0x17],
+            frame_step: 0,
+            square1: square::Square::new(true),
+            square2: square::Square::new(false),
+            wave: wave::Wave::new(),
+            noise: noise::Noise::new(),
+            sample_clock: 0,
+            samples: Vec::new(),
+            sample_sender: None,
+            recorded: None,
+        };
+
+        // register values the dmg boot rom leaves behind, after playing its sound on
+        // channel 1
+        let mut interrupts = Interrupts::default();
+        for (addr, content) in [
+            (0xFF11, 0x80),
+            (0xFF12, 0xF3),
+            (0xFF14, 0x80),
+            (NR50, 0x77),
+            (NR51, 0xF3),
+        ] {
+            let _ = apu.write(addr, content, &mut interrupts);
+        }
+        apu.registers[0x04] = 0;
+        apu
+    }
+
+    // mixed samples are sent in batches. batches are dropped if the receiver falls behind.
+    pub fn set_sample_sender(&mut self, sender: SyncSender<Vec<Sample>>) {
+        self.samples = Vec::with_capacity(SAMPLES_PER_BATCH);
+        self.sample_sender = Some(sender);
+    }
+
+    // keeps every sample from now on for `take_recorded_samples`, on top of sending them.
+    // unlike batches for the sender, none of them are dropped.
+    pub fn set_is_recording(&mut self, is_recording: bool) {
+        self.recorded = is_recording.then(Vec::new);
+    }
+
+    pub fn take_recorded_samples(&mut self) -> Vec<Sample> {
+        self.recorded
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    // length counters can't be written while the apu is off, except on dmg
+    fn is_dmg(&self) -> bool {
+        !self.model.is_cgb_hardware()
+    }
+
+    // length counters are clocked on every other step, so the ones in between get the
+    // odd extra clock when enabling them, see `Length::write_control`
+    fn does_next_step_clock_length(&self) -> bool {
+        self.frame_step.is_multiple_of(2)
+    }
+
+    // called by the bus at 512 hz, on the falling edge of DIV bit 4 (bit 5 in double speed)
+    pub fn clock_frame_sequencer(&mut self) {
+        if !self.is_powered {
+            return;
+        }
+
+        if self.frame_step.is_multiple_of(2) {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.square1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+
+    fn power_off(&mut self) {
+        self.is_powered = false;
+        self.registers = [0; 0x17];
+        let is_dmg = self.is_dmg();
+        self.square1.power_off(is_dmg);
+        self.square2.power_off(is_dmg);
+        self.wave.power_off(is_dmg);
+        self.noise.power_off(is_dmg);
+    }
+
+    fn power_on(&mut self) {
+        self.is_powered = true;
+        self.frame_step = 0;
+    }
+
+    fn read_nr52(&self) -> u8 {
+        ((self.is_powered as u8) << 7)
+            | READ_MASKS[0x16]
+            | (self.noise.is_enabled as u8) << 3
+            | (self.wave.is_enabled as u8) << 2
+            | (self.square2.is_enabled as u8) << 1
+            | self.square1.is_enabled as u8
+    }
+
+    // mixes the channels into a stereo sample, according to NR50 / NR51
+    fn mix(&self) -> Sample {
+        let outputs = [
+            self.square1.output(),
+            self.square2.output(),
+            self.wave.output(),
+            self.noise.output(),
+        ];
+        let panning = self.registers[(NR51 - 0xFF10) as usize];
+        let volumes = self.registers[(NR50 - 0xFF10) as usize];
+
+        let mut sides = [0i32; 2];
+        for (channel, output) in outputs.into_iter().enumerate() {
+            // each dac turns 0-15 into a level between 1 and -1, or nothing when it's off
+            let Some(output) = output else {
+                continue;
+            };
+            let level = 15 - 2 * output as i32;
+            // right is the lower nibble of NR51, left the upper one
+            for (side, level_sum) in sides.iter_mut().enumerate() {
+                if panning & (1 << (channel + 4 * (1 - side))) != 0 {
+                    *level_sum += level;
+                }
+            }
+        }
+
+        // four channels at up to 15 each, times a volume of up to 8, fits into i16 with room
+        let left = sides[0] * (((volumes >> 4) & 0b111) as i32 + 1);
+        let right = sides[1] * ((volumes & 0b111) as i32 + 1);
+        [(left * 64) as i16, (right * 64) as i16]
+    }
+
+    fn take_samples(&mut self, cycles: u32) {
+        if self.sample_sender.is_none() && self.recorded.is_none() {
+            return;
+        }
+
+        self.sample_clock += SAMPLE_RATE * cycles as u64;
+        if self.sample_clock < CLOCK_SPEED {
+            return;
+        }
+        // ticks are a few cycles at most, far below the time between samples
+        self.sample_clock -= CLOCK_SPEED;
+        let sample = if self.is_powered { self.mix() } else { [0; 2] };
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push(sample);
+        }
+
+        let Some(sender) = &self.sample_sender else {
+            return;
+        };
+        self.samples.push(sample);
+
+        if self.samples.len() >= SAMPLES_PER_BATCH {
+            let batch = std::mem::replace(&mut self.samples, Vec::with_capacity(SAMPLES_PER_BATCH));
+            // a full queue means nobody is keeping up, there's no point in piling them up
+            let _ = sender.try_send(batch);
+        }
+    }
+}
+
+impl Device for Apu {
+    fn read(&self, addr: u16) -> Result<u8, BusError> {
+        match addr {
+            NR52 => Ok(self.read_nr52()),
+            0xFF10..NR52 => {
+                let index = (addr - 0xFF10) as usize;
+                Ok(self.registers[index] | READ_MASKS[index])
+            }
+            0xFF27..=0xFF2F => Ok(0xFF),
+            0xFF30..=0xFF3F => Ok(self.wave.read_ram(addr, self.is_dmg())),
+            _ => Err(BusError::Unimplemented(addr)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        addr: u16,
+        content: u8,
+        _interrupts: &mut Interrupts,
+    ) -> Result<(), BusError> {
+        let does_next_step_clock_length = self.does_next_step_clock_length();
+
+        match addr {
+            NR52 => match (self.is_powered, content & 0x80 != 0) {
+                (true, false) => self.power_off(),
+                (false, true) => self.power_on(),
+                _ => (),
+            },
+            // everything else is read only while the apu is off, but the dmg still lets
+            // the length counters be written
+            0xFF11 | 0xFF16 | 0xFF1B | 0xFF20 if !self.is_powered && self.is_dmg() => match addr {
+                0xFF11 => self.square1.load_length(content),
+                0xFF16 => self.square2.load_length(content),
+                0xFF1B => self.wave.load_length(content),
+                _ => self.noise.load_length(content),
+            },
+            0xFF10..NR52 if !self.is_powered => (),
+            0xFF10..NR52 => {
+                self.registers[(addr - 0xFF10) as usize] = content;
+                match addr {
+                    0xFF10..=0xFF14 => {
+                        self.square1
+                            .write(addr - 0xFF10, content, does_next_step_clock_length)
+                    }
+                    0xFF15..=0xFF19 => {
+                        self.square2
+                            .write(addr - 0xFF15, content, does_next_step_clock_length)
+                    }
+                    0xFF1A..=0xFF1E => {
+                        if addr == 0xFF1E && content & 0x80 != 0 && self.is_dmg() {
+                            self.wave.corrupt_on_retrigger();
+                        }
+                        self.wave
+                            .write(addr - 0xFF1A, content, does_next_step_clock_length)
+                    }
+                    0xFF1F..=0xFF23 => {
+                        self.noise
+                            .write(addr - 0xFF1F, content, does_next_step_clock_length)
+                    }
+                    _ => (),
+                }
+            }
+            0xFF27..=0xFF2F => (),
+            0xFF30..=0xFF3F => {
+                let is_dmg = self.is_dmg();
+                self.wave.write_ram(addr, content, is_dmg);
+            }
+            _ => return Err(BusError::Unimplemented(addr)),
+        }
+        Ok(())
+    }
+
+    // wave ram as it is, without the restrictions of a playing channel
+    fn peek(&self, addr: u16) -> Result<u8, BusError> {
+        match addr {
+            0xFF30..=0xFF3F => Ok(self.wave.ram[(addr & 0xF) as usize]),
+            _ => self.read(addr),
+        }
+    }
+
+    fn poke(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        match addr {
+            0xFF30..=0xFF3F => {
+                self.wave.ram[(addr & 0xF) as usize] = content;
+                Ok(())
+            }
+            _ => self.write(addr, content, &mut Interrupts::default()),
+        }
+    }
+
+    fn tick(&mut self, cycles: u8, _interrupts: &mut Interrupts) {
+        if self.is_powered {
+            let cycles = cycles as u32;
+            self.square1.tick(cycles);
+            self.square2.tick(cycles);
+            self.wave.tick(cycles);
+            self.noise.tick(cycles);
+        }
+        self.take_samples(cycles as u32);
+    }
+
+    // the channels keep running at the same speed, only the frame sequencer speeds up,
+    // which the bus takes care of
+    fn runs_at_double_speed(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(apu: &mut Apu, addr: u16, content: u8) {
+        apu.write(addr, content, &mut Interrupts::default())
+            .unwrap();
+    }
+
+    // NR52's bit for `channel`, counting from 0
+    fn is_playing(apu: &Apu, channel: u8) -> bool {
+        apu.read(NR52).unwrap() & (1 << channel) != 0
+    }
+
+    #[test]
+    fn samples_are_sent_in_batches() {
+        let mut apu = Apu::new(Model::Dmg);
+        let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+        apu.set_sample_sender(sender);
+
+        // a batch is due every 800 / 48000 of a second
+        let cycles = (CLOCK_SPEED * SAMPLES_PER_BATCH as u64).div_ceil(SAMPLE_RATE);
+        for _ in 0..cycles.div_ceil(4) {
+            apu.tick(4, &mut Interrupts::default());
+        }
+        assert_eq!(receiver.try_recv().unwrap().len(), SAMPLES_PER_BATCH);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn recorded_samples_are_kept_until_taken() {
+        let mut apu = Apu::new(Model::Dmg);
+        apu.set_is_recording(true);
+
+        // one sample every 1 / 48000 of a second, nobody's listening to the batches
+        let cycles = (CLOCK_SPEED * 3).div_ceil(SAMPLE_RATE);
+        for _ in 0..cycles.div_ceil(4) {
+            apu.tick(4, &mut Interrupts::default());
+        }
+        assert_eq!(apu.take_recorded_samples().len(), 3);
+        assert!(apu.take_recorded_samples().is_empty());
+
+        apu.set_is_recording(false);
+        apu.tick(255, &mut Interrupts::default());
+        assert!(apu.take_recorded_samples().is_empty());
+    }
+
+    #[test]
+    fn registers_read_back_with_their_unused_bits_set() {
+        let mut apu = Apu::new(Model::Cgb);
+        for addr in 0xFF10..NR52 {
+            write(&mut apu, addr, 0);
+        }
+        for addr in 0xFF10..NR52 {
+            let index = (addr - 0xFF10) as usize;
+            assert_eq!(apu.read(addr).unwrap(), READ_MASKS[index], "{:04X}", addr);
+        }
+        assert_eq!(apu.read(NR52).unwrap(), 0xF0);
+        assert_eq!(apu.read(0xFF27).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn powering_off_clears_the_registers() {
+        let mut apu = Apu::new(Model::Cgb);
+        write(&mut apu, NR52, 0);
+        for addr in 0xFF10..NR52 {
+            let index = (addr - 0xFF10) as usize;
+            assert_eq!(apu.read(addr).unwrap(), READ_MASKS[index], "{:04X}", addr);
+        }
+        assert_eq!(apu.read(NR52).unwrap(), 0x70);
+
+        // and keeps them that way until it's back on
+        write(&mut apu, NR50, 0x77);
+        assert_eq!(apu.read(NR50).unwrap(), 0);
+        write(&mut apu, NR52, 0x80);
+        assert_eq!(apu.read(NR50).unwrap(), 0);
+        write(&mut apu, NR50, 0x77);
+        assert_eq!(apu.read(NR50).unwrap(), 0x77);
+    }
+
+    #[test]
+    fn dmg_lengths_can_be_written_while_powered_off() {
+        let mut apu = Apu::new(Model::Dmg);
+        write(&mut apu, NR52, 0);
+        // a length of 1
+        write(&mut apu, 0xFF16, 0x3F);
+        assert_eq!(apu.read(0xFF16).unwrap(), 0x3F);
+        write(&mut apu, NR52, 0x80);
+
+        write(&mut apu, 0xFF17, 0xF0);
+        write(&mut apu, 0xFF19, 0xC0);
+        assert!(is_playing(&apu, 1));
+        apu.clock_frame_sequencer();
+        assert!(!is_playing(&apu, 1));
+    }
+
+    #[test]
+    fn length_is_clocked_on_even_steps() {
+        let mut apu = Apu::new(Model::Dmg);
+        write(&mut apu, 0xFF16, 0x3F);
+        write(&mut apu, 0xFF17, 0xF0);
+        write(&mut apu, 0xFF19, 0xC0);
+
+        apu.frame_step = 1;
+        apu.clock_frame_sequencer();
+        assert!(is_playing(&apu, 1));
+        apu.clock_frame_sequencer();
+        assert!(!is_playing(&apu, 1));
+    }
+
+    #[test]
+    fn sweep_is_clocked_on_steps_2_and_6() {
+        for start in [7, 3] {
+            let mut apu = Apu::new(Model::Dmg);
+            apu.frame_step = start;
+            // sweeping up every step by half the frequency, 1100 overflows on the first one
+            write(&mut apu, 0xFF10, 0x11);
+            write(&mut apu, 0xFF12, 0xF0);
+            write(&mut apu, 0xFF13, 0x4C);
+            write(&mut apu, 0xFF14, 0x84);
+
+            for _ in 0..3 {
+                apu.clock_frame_sequencer();
+                assert!(is_playing(&apu, 0));
+            }
+            apu.clock_frame_sequencer();
+            assert!(!is_playing(&apu, 0));
+        }
+    }
+
+    #[test]
+    fn envelope_is_clocked_on_step_7() {
+        let mut apu = Apu::new(Model::Dmg);
+        // a duty cycle that starts high, at full volume and fading out every step
+        write(&mut apu, 0xFF16, 0x40);
+        write(&mut apu, 0xFF17, 0xF1);
+        write(&mut apu, 0xFF19, 0x80);
+
+        for _ in 0..7 {
+            apu.clock_frame_sequencer();
+            assert_eq!(apu.square2.output(), Some(15));
+        }
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.square2.output(), Some(14));
+    }
+}
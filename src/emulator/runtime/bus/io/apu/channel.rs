@@ -0,0 +1,169 @@
+// the parts the channels have in common: length counters and volume envelopes
+
+// turns a channel off once it has played for as long as NRx1 says. clocked at 256 hz by
+// the frame sequencer, and only counting down while enabled through NRx4.
+#[derive(Default)]
+pub struct Length {
+    // 64 for everything but the wave channel, which has 256
+    max: u16,
+    counter: u16,
+    is_enabled: bool,
+}
+
+impl Length {
+    pub fn new(max: u16) -> Self {
+        Self {
+            max,
+            ..Self::default()
+        }
+    }
+
+    // the length field of NRx1, which counts up to the maximum
+    pub fn load(&mut self, length: u8) {
+        self.counter = self.max - length as u16;
+    }
+
+    // the counter survives the apu being turned off on dmg, but not on cgb
+    pub fn power_off(&mut self, does_keep_counter: bool) {
+        self.is_enabled = false;
+        if !does_keep_counter {
+            self.counter = 0;
+        }
+    }
+
+    // returns whether the channel has to be turned off
+    pub fn clock(&mut self) -> bool {
+        if !self.is_enabled || self.counter == 0 {
+            return false;
+        }
+        self.counter -= 1;
+        self.counter == 0
+    }
+
+    // handles the length bits of a NRx4 write. the frame sequencer only clocks length on
+    // every other step, and enabling the counter or triggering the channel while the next
+    // step won't clock it gets an extra clock in, as if it had happened earlier. returns
+    // whether the channel has to be turned off.
+    pub fn write_control(
+        &mut self,
+        is_enabled: bool,
+        is_trigger: bool,
+        does_next_step_clock_length: bool,
+    ) -> bool {
+        let was_enabled = std::mem::replace(&mut self.is_enabled, is_enabled);
+        let mut is_expired = false;
+
+        if !was_enabled && is_enabled && !does_next_step_clock_length && self.counter > 0 {
+            self.counter -= 1;
+            is_expired = self.counter == 0 && !is_trigger;
+        }
+
+        if is_trigger && self.counter == 0 {
+            self.counter = self.max;
+            if is_enabled && !does_next_step_clock_length {
+                self.counter -= 1;
+            }
+        }
+
+        is_expired
+    }
+}
+
+// fades a channel's volume in or out. clocked at 64 hz by the frame sequencer.
+#[derive(Default)]
+pub struct Envelope {
+    // NRx2
+    initial_volume: u8,
+    is_increasing: bool,
+    period: u8,
+
+    pub volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn write(&mut self, content: u8) {
+        self.initial_volume = content >> 4;
+        self.is_increasing = content & 0b1000 != 0;
+        self.period = content & 0b111;
+    }
+
+    // the dac is off when NRx2 neither starts at some volume nor fades in, which also
+    // turns the channel off
+    pub fn is_dac_enabled(&self) -> bool {
+        self.initial_volume != 0 || self.is_increasing
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    pub fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.period;
+
+        if self.is_increasing && self.volume < 15 {
+            self.volume += 1;
+        } else if !self.is_increasing && self.volume > 0 {
+            self.volume -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enabling_length_gets_an_extra_clock_between_steps() {
+        let mut length = Length::new(64);
+        length.load(60);
+        assert!(!length.write_control(true, false, false));
+        assert_eq!(length.counter, 3);
+
+        // no extra clock if the next step clocks length anyway, or if it was enabled already
+        let mut length = Length::new(64);
+        length.load(60);
+        assert!(!length.write_control(true, false, true));
+        assert!(!length.write_control(true, false, false));
+        assert_eq!(length.counter, 4);
+    }
+
+    #[test]
+    fn the_extra_clock_can_expire_the_counter() {
+        let mut length = Length::new(64);
+        length.load(63);
+        assert!(length.write_control(true, false, false));
+        assert_eq!(length.counter, 0);
+
+        // unless it's a trigger, which reloads it and clocks it right away
+        let mut length = Length::new(64);
+        length.load(63);
+        assert!(!length.write_control(true, true, false));
+        assert_eq!(length.counter, 63);
+    }
+
+    #[test]
+    fn triggering_reloads_an_expired_counter() {
+        let mut length = Length::new(256);
+        assert!(!length.write_control(false, true, false));
+        assert_eq!(length.counter, 256);
+    }
+
+    #[test]
+    fn the_counter_survives_power_off_on_dmg_only() {
+        let mut length = Length::new(64);
+        length.load(10);
+        length.power_off(true);
+        assert_eq!((length.counter, length.is_enabled), (54, false));
+        length.power_off(false);
+        assert_eq!(length.counter, 0);
+    }
+}
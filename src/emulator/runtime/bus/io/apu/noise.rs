@@ -0,0 +1,124 @@
+use super::channel::{Envelope, Length};
+
+// channel 4 plays pseudo random noise from a linear feedback shift register
+
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+#[derive(Default)]
+pub struct Noise {
+    pub is_enabled: bool,
+    // NR43
+    shift: u8,
+    // 7 bit mode, which repeats a lot sooner and sounds more like a tone
+    is_short: bool,
+    divisor: u8,
+    // t-cycles until the next shift
+    timer: u32,
+    lfsr: u16,
+    length: Length,
+    envelope: Envelope,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Self {
+            length: Length::new(64),
+            ..Self::default()
+        }
+    }
+
+    fn period(&self) -> u32 {
+        DIVISORS[self.divisor as usize] << self.shift
+    }
+
+    pub fn write(&mut self, register: u16, content: u8, does_next_step_clock_length: bool) {
+        match register {
+            1 => self.length.load(content & 0b11_1111),
+            2 => {
+                self.envelope.write(content);
+                if !self.envelope.is_dac_enabled() {
+                    self.is_enabled = false;
+                }
+            }
+            3 => {
+                self.shift = content >> 4;
+                self.is_short = content & 0b1000 != 0;
+                self.divisor = content & 0b111;
+            }
+            4 => {
+                let is_trigger = content & 0x80 != 0;
+                if self.length.write_control(
+                    content & 0x40 != 0,
+                    is_trigger,
+                    does_next_step_clock_length,
+                ) {
+                    self.is_enabled = false;
+                }
+                if is_trigger {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn load_length(&mut self, content: u8) {
+        self.length.load(content & 0b11_1111);
+    }
+
+    fn trigger(&mut self) {
+        self.is_enabled = self.envelope.is_dac_enabled();
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+        self.envelope.trigger();
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.is_enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn power_off(&mut self, does_keep_length: bool) {
+        let mut length = std::mem::take(&mut self.length);
+        length.power_off(does_keep_length);
+        *self = Self::new();
+        self.length = length;
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        if !self.is_enabled {
+            return;
+        }
+
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+
+            // the xor of the lowest two bits is shifted in at the top, and in 7 bit mode
+            // into bit 6 as well
+            let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (bit << 14);
+            if self.is_short {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (bit << 6);
+            }
+        }
+        self.timer -= cycles;
+    }
+
+    pub fn output(&self) -> Option<u8> {
+        if !self.envelope.is_dac_enabled() {
+            return None;
+        }
+        if !self.is_enabled {
+            return Some(0);
+        }
+        // the lowest bit, inverted
+        Some((!self.lfsr & 1) as u8 * self.envelope.volume)
+    }
+}
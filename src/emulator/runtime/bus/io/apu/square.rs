@@ -0,0 +1,203 @@
+use super::channel::{Envelope, Length};
+
+// channels 1 and 2 play a square wave with one of four duty cycles. channel 1 can also
+// sweep its frequency up or down.
+
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+// NR10, channel 1 only
+#[derive(Default)]
+struct Sweep {
+    period: u8,
+    is_decreasing: bool,
+    shift: u8,
+
+    is_enabled: bool,
+    timer: u8,
+    // the frequency calculations work off a copy of the frequency
+    shadow: u16,
+    // clearing the decrease bit after a calculation used it turns the channel off
+    has_decreased: bool,
+}
+
+#[derive(Default)]
+pub struct Square {
+    pub is_enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    // 11 bits, from NRx3 and NRx4
+    frequency: u16,
+    // t-cycles until the next duty step
+    timer: u32,
+    length: Length,
+    envelope: Envelope,
+    sweep: Option<Sweep>,
+}
+
+impl Square {
+    pub fn new(has_sweep: bool) -> Self {
+        Self {
+            length: Length::new(64),
+            sweep: has_sweep.then(Sweep::default),
+            ..Self::default()
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    // `register` is the offset from NRx0, so NR11 and NR21 are both 1
+    pub fn write(&mut self, register: u16, content: u8, does_next_step_clock_length: bool) {
+        match register {
+            0 => {
+                if let Some(sweep) = &mut self.sweep {
+                    sweep.period = (content >> 4) & 0b111;
+                    sweep.is_decreasing = content & 0b1000 != 0;
+                    sweep.shift = content & 0b111;
+                    if !sweep.is_decreasing && sweep.has_decreased {
+                        self.is_enabled = false;
+                    }
+                }
+            }
+            1 => {
+                self.duty = content >> 6;
+                self.length.load(content & 0b11_1111);
+            }
+            2 => {
+                self.envelope.write(content);
+                if !self.envelope.is_dac_enabled() {
+                    self.is_enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | content as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((content as u16 & 0b111) << 8);
+                let is_trigger = content & 0x80 != 0;
+                if self.length.write_control(
+                    content & 0x40 != 0,
+                    is_trigger,
+                    does_next_step_clock_length,
+                ) {
+                    self.is_enabled = false;
+                }
+                if is_trigger {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // only the length part of NRx1 can be written while the apu is off, on dmg
+    pub fn load_length(&mut self, content: u8) {
+        self.length.load(content & 0b11_1111);
+    }
+
+    fn trigger(&mut self) {
+        self.is_enabled = self.envelope.is_dac_enabled();
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        let frequency = self.frequency;
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow = frequency;
+            sweep.timer = if sweep.period == 0 { 8 } else { sweep.period };
+            sweep.is_enabled = sweep.period != 0 || sweep.shift != 0;
+            sweep.has_decreased = false;
+            // an overflow check happens right away, without updating the frequency
+            if sweep.shift != 0 && self.next_sweep_frequency() > 2047 {
+                self.is_enabled = false;
+            }
+        }
+    }
+
+    // the frequency after the next sweep step, which turns the channel off if it overflows
+    fn next_sweep_frequency(&mut self) -> u16 {
+        let Some(sweep) = &mut self.sweep else {
+            return self.frequency;
+        };
+        let delta = sweep.shadow >> sweep.shift;
+        if sweep.is_decreasing {
+            sweep.has_decreased = true;
+            sweep.shadow - delta
+        } else {
+            sweep.shadow + delta
+        }
+    }
+
+    pub fn clock_sweep(&mut self) {
+        let Some(sweep) = &mut self.sweep else {
+            return;
+        };
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer > 0 {
+            return;
+        }
+        sweep.timer = if sweep.period == 0 { 8 } else { sweep.period };
+        if !sweep.is_enabled || sweep.period == 0 {
+            return;
+        }
+
+        let shift = sweep.shift;
+        let frequency = self.next_sweep_frequency();
+        if frequency > 2047 {
+            self.is_enabled = false;
+            return;
+        }
+        if shift != 0 {
+            self.frequency = frequency;
+            if let Some(sweep) = &mut self.sweep {
+                sweep.shadow = frequency;
+            }
+            // the new frequency is checked for overflow once more, but not used
+            if self.next_sweep_frequency() > 2047 {
+                self.is_enabled = false;
+            }
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.is_enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    // turning the apu off resets everything but the length counter, see `Length::power_off`
+    pub fn power_off(&mut self, does_keep_length: bool) {
+        let mut length = std::mem::take(&mut self.length);
+        length.power_off(does_keep_length);
+        *self = Self::new(self.sweep.is_some());
+        self.length = length;
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+        self.timer -= cycles;
+    }
+
+    // the digital output, or None with the dac off
+    pub fn output(&self) -> Option<u8> {
+        if !self.envelope.is_dac_enabled() {
+            return None;
+        }
+        if !self.is_enabled {
+            return Some(0);
+        }
+        Some(DUTY_CYCLES[self.duty as usize][self.duty_step as usize] * self.envelope.volume)
+    }
+}
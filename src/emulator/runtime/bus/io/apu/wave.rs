@@ -0,0 +1,166 @@
+use super::channel::Length;
+
+// channel 3 plays back 32 4-bit samples from wave ram, at one of four volumes
+
+// t-cycles between triggering the channel and it reading its first sample
+const TRIGGER_DELAY: u32 = 6;
+
+#[derive(Default)]
+pub struct Wave {
+    pub is_enabled: bool,
+    // NR30 bit 7
+    is_dac_enabled: bool,
+    // NR32, 0 is mute and the others shift the sample right by one less
+    volume: u8,
+    frequency: u16,
+    // t-cycles until the next sample is read
+    timer: u32,
+    // which of the 32 samples is playing
+    position: u8,
+    // the last sample read, which is what's played until the next one
+    sample: u8,
+    // t-cycles since the last read from wave ram, the dmg only lets the cpu in right then
+    cycles_since_read: u32,
+    length: Length,
+    pub ram: [u8; 16],
+}
+
+impl Wave {
+    pub fn new() -> Self {
+        Self {
+            length: Length::new(256),
+            cycles_since_read: u32::MAX,
+            ..Self::default()
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    pub fn write(&mut self, register: u16, content: u8, does_next_step_clock_length: bool) {
+        match register {
+            0 => {
+                self.is_dac_enabled = content & 0x80 != 0;
+                if !self.is_dac_enabled {
+                    self.is_enabled = false;
+                }
+            }
+            1 => self.length.load(content),
+            2 => self.volume = (content >> 5) & 0b11,
+            3 => self.frequency = (self.frequency & 0x700) | content as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((content as u16 & 0b111) << 8);
+                let is_trigger = content & 0x80 != 0;
+                if self.length.write_control(
+                    content & 0x40 != 0,
+                    is_trigger,
+                    does_next_step_clock_length,
+                ) {
+                    self.is_enabled = false;
+                }
+                if is_trigger {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn load_length(&mut self, content: u8) {
+        self.length.load(content);
+    }
+
+    fn trigger(&mut self) {
+        self.is_enabled = self.is_dac_enabled;
+        self.timer = self.period() + TRIGGER_DELAY;
+        self.position = 0;
+    }
+
+    // retriggering the dmg while it's about to read a sample overwrites the start of wave
+    // ram with whatever it was reading: the byte itself if it's one of the first four,
+    // otherwise the four bytes around it
+    pub fn corrupt_on_retrigger(&mut self) {
+        if !self.is_enabled || self.timer != 2 {
+            return;
+        }
+        let index = ((self.position as usize + 1) % 32) / 2;
+        if index < 4 {
+            self.ram[0] = self.ram[index];
+        } else {
+            let block = index & !0b11;
+            self.ram.copy_within(block..block + 4, 0);
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.is_enabled = false;
+        }
+    }
+
+    pub fn power_off(&mut self, does_keep_length: bool) {
+        let mut length = std::mem::take(&mut self.length);
+        length.power_off(does_keep_length);
+        // wave ram isn't touched by the power switch
+        let ram = self.ram;
+        *self = Self::new();
+        self.length = length;
+        self.ram = ram;
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        if !self.is_enabled {
+            return;
+        }
+        self.cycles_since_read = self.cycles_since_read.saturating_add(cycles);
+
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+            let byte = self.ram[self.position as usize / 2];
+            self.sample = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0xF
+            };
+            self.cycles_since_read = cycles;
+        }
+        self.timer -= cycles;
+    }
+
+    // while the channel is playing, the cpu can only get at the byte it's reading. the dmg
+    // is even stricter and only allows it in the same cycle the channel reads it.
+    fn ram_index(&self, addr: u16, is_dmg: bool) -> Option<usize> {
+        if !self.is_enabled {
+            return Some((addr & 0xF) as usize);
+        }
+        if is_dmg && self.cycles_since_read > 1 {
+            return None;
+        }
+        Some(self.position as usize / 2)
+    }
+
+    pub fn read_ram(&self, addr: u16, is_dmg: bool) -> u8 {
+        self.ram_index(addr, is_dmg)
+            .map_or(0xFF, |index| self.ram[index])
+    }
+
+    pub fn write_ram(&mut self, addr: u16, content: u8, is_dmg: bool) {
+        if let Some(index) = self.ram_index(addr, is_dmg) {
+            self.ram[index] = content;
+        }
+    }
+
+    pub fn output(&self) -> Option<u8> {
+        if !self.is_dac_enabled {
+            return None;
+        }
+        if !self.is_enabled || self.volume == 0 {
+            return Some(0);
+        }
+        Some(self.sample >> (self.volume - 1))
+    }
+}
@@ -0,0 +1,63 @@
+use crate::emulator::runtime::bus::device::Device;
+use crate::emulator::runtime::bus::error::BusError;
+use crate::emulator::runtime::bus::io::interrupts::Interrupts;
+use crate::emulator::runtime::model::Model;
+
+// leftover cgb system registers: KEY0, which the boot rom uses to lock in cgb or
+// compatibility mode, and the undocumented FF72-FF75, which don't do anything but hold
+// on to what's written to them. all of them read 0xFF on dmg.
+pub struct CgbRegisters {
+    model: Model,
+    key0: u8,
+    undocumented: [u8; 4],
+}
+
+impl CgbRegisters {
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            // what the boot rom writes before locking the register
+            key0: if model.is_cgb() { 0x80 } else { 0x04 },
+            undocumented: [0; 4],
+        }
+    }
+}
+
+impl Device for CgbRegisters {
+    fn read(&self, addr: u16) -> Result<u8, BusError> {
+        match addr {
+            0xFF4C | 0xFF72..=0xFF75 if !self.model.is_cgb_hardware() => Ok(0xFF),
+            0xFF4C => Ok(self.key0),
+            0xFF72 | 0xFF73 => Ok(self.undocumented[(addr - 0xFF72) as usize]),
+            // only available to cgb games
+            0xFF74 if self.model.is_cgb() => Ok(self.undocumented[2]),
+            0xFF74 => Ok(0xFF),
+            // only bits 4-6 exist
+            0xFF75 => Ok(0b1000_1111 | self.undocumented[3]),
+            _ => Err(BusError::Unimplemented(addr)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        addr: u16,
+        content: u8,
+        _interrupts: &mut Interrupts,
+    ) -> Result<(), BusError> {
+        self.poke(addr, content)
+    }
+
+    fn poke(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        match addr {
+            0xFF4C | 0xFF72..=0xFF75 if !self.model.is_cgb_hardware() => (),
+            // KEY0 is locked once the boot rom is done, which it always is here
+            0xFF4C => (),
+            0xFF72 | 0xFF73 => self.undocumented[(addr - 0xFF72) as usize] = content,
+            0xFF74 if self.model.is_cgb() => self.undocumented[2] = content,
+            0xFF74 => (),
+            0xFF75 => self.undocumented[3] = content & 0b0111_0000,
+            _ => return Err(BusError::Unimplemented(addr)),
+        }
+        Ok(())
+    }
+}
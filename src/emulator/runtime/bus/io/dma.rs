@@ -0,0 +1,120 @@
+use crate::emulator::runtime::bus::device::Device;
+use crate::emulator::runtime::bus::error::BusError;
+use crate::emulator::runtime::bus::io::interrupts::Interrupts;
+
+// oam dma through DMA (0xFF46), which copies 160 bytes from 0xXX00 into oam. like vram
+// dma, the copying is done by the bus, this only keeps track of which byte is due. after
+// an m-cycle to get going, one byte is copied every m-cycle, and oam is off limits to the
+// cpu until the last one is through.
+#[derive(Default)]
+pub struct OamDma {
+    register: u8,
+    transfer: Option<Transfer>,
+}
+
+pub const LENGTH: u16 = 0xA0;
+
+struct Transfer {
+    source: u16,
+    copied: u16,
+    // t-cycles since the transfer was started
+    cycles: u32,
+}
+
+impl OamDma {
+    pub fn is_active(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    // returns where the next byte is copied from and its offset into oam, if it's due
+    pub fn next_byte(&mut self) -> Option<(u16, u16)> {
+        let transfer = self.transfer.as_mut()?;
+        if transfer.cycles < (transfer.copied as u32 + 2) * 4 {
+            return None;
+        }
+        let byte = (transfer.source + transfer.copied, transfer.copied);
+        transfer.copied += 1;
+        if transfer.copied == LENGTH {
+            self.transfer = None;
+        }
+        Some(byte)
+    }
+}
+
+impl Device for OamDma {
+    fn read(&self, addr: u16) -> Result<u8, BusError> {
+        match addr {
+            0xFF46 => Ok(self.register),
+            _ => Err(BusError::Unimplemented(addr)),
+        }
+    }
+
+    // starting a new transfer while one is running restarts it from the top
+    fn write(
+        &mut self,
+        addr: u16,
+        content: u8,
+        _interrupts: &mut Interrupts,
+    ) -> Result<(), BusError> {
+        match addr {
+            0xFF46 => {
+                self.register = content;
+                self.transfer = Some(Transfer {
+                    source: (content as u16) << 8,
+                    copied: 0,
+                    cycles: 0,
+                });
+            }
+            _ => return Err(BusError::Unimplemented(addr)),
+        }
+        Ok(())
+    }
+
+    // only sets the register, nothing gets copied
+    fn poke(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        match addr {
+            0xFF46 => self.register = content,
+            _ => return Err(BusError::Unimplemented(addr)),
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, cycles: u8, _interrupts: &mut Interrupts) {
+        if let Some(transfer) = &mut self.transfer {
+            transfer.cycles += cycles as u32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_are_due_one_m_cycle_apart_after_a_delay() {
+        let mut dma = OamDma::default();
+        dma.write(0xFF46, 0xC1, &mut Interrupts::default()).unwrap();
+        assert_eq!(dma.read(0xFF46).unwrap(), 0xC1);
+
+        dma.tick(4, &mut Interrupts::default());
+        assert_eq!(dma.next_byte(), None);
+        dma.tick(4, &mut Interrupts::default());
+        assert_eq!(dma.next_byte(), Some((0xC100, 0)));
+        assert_eq!(dma.next_byte(), None);
+
+        for offset in 1..LENGTH {
+            assert!(dma.is_active());
+            dma.tick(4, &mut Interrupts::default());
+            assert_eq!(dma.next_byte(), Some((0xC100 + offset, offset)));
+        }
+        assert!(!dma.is_active());
+    }
+
+    #[test]
+    fn poking_the_register_doesnt_start_a_transfer() {
+        let mut dma = OamDma::default();
+        dma.poke(0xFF46, 0xC1).unwrap();
+        assert_eq!(dma.read(0xFF46).unwrap(), 0xC1);
+        assert!(!dma.is_active());
+    }
+}
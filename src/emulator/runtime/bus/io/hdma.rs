@@ -0,0 +1,212 @@
+use crate::emulator::runtime::bus::device::Device;
+use crate::emulator::runtime::bus::error::BusError;
+use crate::emulator::runtime::bus::io::interrupts::Interrupts;
+use crate::emulator::runtime::model::Model;
+
+// cgb vram dma registers. the copying itself is done by the bus, since the source can be
+// anywhere in memory and the cpu has to be held up while it runs. a write to HDMA5
+// leaves a transfer behind for the bus to pick up with `take_transfer`.
+// all of them read 0xFF on dmg.
+pub struct Hdma {
+    model: Model,
+    // lower 4 bits are always 0, transfers go in blocks of 16 bytes
+    source: u16,
+    // offset into vram
+    destination: u16,
+    // blocks left, minus one. reads as 0x7F once a transfer is done.
+    length: u8,
+    is_hblank_active: bool,
+    transfer: Option<Transfer>,
+}
+
+pub const BLOCK_SIZE: u16 = 16;
+
+// what a write to HDMA5 asks the bus to do
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transfer {
+    // everything at once, while the cpu waits
+    General,
+    // one block at the start of every hblank
+    HBlank,
+}
+
+impl Hdma {
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            source: 0,
+            destination: 0,
+            length: 0,
+            is_hblank_active: false,
+            transfer: None,
+        }
+    }
+
+    pub fn is_hblank_active(&self) -> bool {
+        self.is_hblank_active
+    }
+
+    // the length a transfer started with, in blocks
+    pub fn blocks(&self) -> u16 {
+        self.length as u16 + 1
+    }
+
+    pub fn take_transfer(&mut self) -> Option<Transfer> {
+        self.transfer.take()
+    }
+
+    // returns where the next block is copied from and to, and moves on to the one after
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.source, 0x8000 + self.destination);
+        self.source = self.source.wrapping_add(BLOCK_SIZE);
+        self.destination = (self.destination + BLOCK_SIZE) & 0x1FF0;
+
+        self.length = self.length.wrapping_sub(1) & 0x7F;
+        if self.length == 0x7F {
+            self.is_hblank_active = false;
+        }
+        block
+    }
+}
+
+impl Device for Hdma {
+    fn read(&self, addr: u16) -> Result<u8, BusError> {
+        match addr {
+            0xFF51..=0xFF55 if !self.model.is_cgb() => Ok(0xFF),
+            // bit 7 is cleared while an hblank transfer is still going
+            0xFF55 => Ok(((!self.is_hblank_active as u8) << 7) | self.length),
+            // the address registers are write only
+            0xFF51..=0xFF54 => Ok(0xFF),
+            _ => Err(BusError::Unimplemented(addr)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        addr: u16,
+        content: u8,
+        _interrupts: &mut Interrupts,
+    ) -> Result<(), BusError> {
+        match addr {
+            0xFF51..=0xFF55 if !self.model.is_cgb() => (),
+            0xFF51 => self.source = (self.source & 0x00FF) | (content as u16) << 8,
+            0xFF52 => self.source = (self.source & 0xFF00) | (content & 0xF0) as u16,
+            0xFF53 => {
+                self.destination = (self.destination & 0x00FF) | ((content & 0x1F) as u16) << 8
+            }
+            0xFF54 => self.destination = (self.destination & 0xFF00) | (content & 0xF0) as u16,
+            // clearing bit 7 during an hblank transfer cancels it instead of starting a
+            // general one, the remaining length stays readable
+            0xFF55 if self.is_hblank_active && content & 0x80 == 0 => {
+                self.is_hblank_active = false;
+            }
+            0xFF55 => {
+                self.length = content & 0x7F;
+                self.transfer = if content & 0x80 != 0 {
+                    self.is_hblank_active = true;
+                    Some(Transfer::HBlank)
+                } else {
+                    Some(Transfer::General)
+                };
+            }
+            _ => return Err(BusError::Unimplemented(addr)),
+        }
+        Ok(())
+    }
+    // sets the registers without starting anything
+    fn poke(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        self.write(addr, content, &mut Interrupts::default())?;
+        self.transfer = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(hdma: &mut Hdma, addr: u16, content: u8) {
+        hdma.write(addr, content, &mut Interrupts::default())
+            .unwrap();
+    }
+
+    #[test]
+    fn addresses_are_masked_to_blocks_in_vram() {
+        let mut hdma = Hdma::new(Model::Cgb);
+        write(&mut hdma, 0xFF51, 0xD1);
+        write(&mut hdma, 0xFF52, 0x2F);
+        // the destination only has 13 bits, the top three are ignored
+        write(&mut hdma, 0xFF53, 0xFF);
+        write(&mut hdma, 0xFF54, 0x3F);
+        write(&mut hdma, 0xFF55, 0x01);
+
+        assert_eq!(hdma.take_transfer(), Some(Transfer::General));
+        assert_eq!(hdma.blocks(), 2);
+        assert_eq!(hdma.next_block(), (0xD120, 0x9F30));
+        assert_eq!(hdma.next_block(), (0xD130, 0x9F40));
+        assert_eq!(hdma.read(0xFF55).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn destination_wraps_around_within_vram() {
+        let mut hdma = Hdma::new(Model::Cgb);
+        write(&mut hdma, 0xFF53, 0x1F);
+        write(&mut hdma, 0xFF54, 0xF0);
+        write(&mut hdma, 0xFF55, 0x01);
+        assert_eq!(hdma.next_block().1, 0x9FF0);
+        assert_eq!(hdma.next_block().1, 0x8000);
+    }
+
+    #[test]
+    fn address_registers_are_write_only() {
+        let mut hdma = Hdma::new(Model::Cgb);
+        for addr in 0xFF51..=0xFF54 {
+            write(&mut hdma, addr, 0x12);
+            assert_eq!(hdma.read(addr).unwrap(), 0xFF);
+        }
+    }
+
+    #[test]
+    fn hblank_transfers_report_their_progress_and_can_be_cancelled() {
+        let mut hdma = Hdma::new(Model::Cgb);
+        write(&mut hdma, 0xFF55, 0x82);
+        assert_eq!(hdma.take_transfer(), Some(Transfer::HBlank));
+        assert!(hdma.is_hblank_active());
+        assert_eq!(hdma.read(0xFF55).unwrap(), 0x02);
+
+        hdma.next_block();
+        assert_eq!(hdma.read(0xFF55).unwrap(), 0x01);
+
+        // clearing bit 7 stops it and doesn't start a general transfer
+        write(&mut hdma, 0xFF55, 0x00);
+        assert_eq!(hdma.take_transfer(), None);
+        assert!(!hdma.is_hblank_active());
+        assert_eq!(hdma.read(0xFF55).unwrap(), 0x81);
+    }
+
+    #[test]
+    fn hblank_transfers_end_after_the_last_block() {
+        let mut hdma = Hdma::new(Model::Cgb);
+        write(&mut hdma, 0xFF55, 0x81);
+        hdma.next_block();
+        assert!(hdma.is_hblank_active());
+        hdma.next_block();
+        assert!(!hdma.is_hblank_active());
+        assert_eq!(hdma.read(0xFF55).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn pokes_dont_start_transfers() {
+        let mut hdma = Hdma::new(Model::Cgb);
+        hdma.poke(0xFF55, 0x05).unwrap();
+        assert_eq!(hdma.take_transfer(), None);
+    }
+
+    #[test]
+    fn dmg_games_have_no_hdma() {
+        let mut hdma = Hdma::new(Model::CgbCompat);
+        write(&mut hdma, 0xFF55, 0x05);
+        assert_eq!(hdma.take_transfer(), None);
+        assert_eq!(hdma.read(0xFF55).unwrap(), 0xFF);
+    }
+}
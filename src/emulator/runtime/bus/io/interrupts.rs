@@ -1,5 +1,6 @@
 use crate::emulator::runtime::bus::error::BusError;
 
+#[derive(Default)]
 pub struct Interrupts {
     pub ime: bool,
     pub registers: [Interrupt; 5],
@@ -32,15 +33,6 @@ pub struct Interrupt {
     pub is_requested: bool,
 }
 
-impl Default for Interrupts {
-    fn default() -> Self {
-        Self {
-            ime: false,
-            registers: [Interrupt::default(); 5],
-        }
-    }
-}
-
 impl Interrupts {
     pub fn read(&self, addr: u16) -> Result<u8, BusError> {
         match addr {
@@ -86,6 +78,12 @@ impl Interrupts {
         }
     }
 
+    pub fn has_pending(&self) -> bool {
+        self.registers
+            .iter()
+            .any(|int| int.is_enabled && int.is_requested)
+    }
+
     pub fn get(&self, kind: InterruptKind) -> &Interrupt {
         &self.registers[kind as usize]
     }
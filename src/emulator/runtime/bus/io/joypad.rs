@@ -0,0 +1,346 @@
+use std::collections::VecDeque;
+
+use crate::emulator::runtime::bus::device::Device;
+use crate::emulator::runtime::bus::error::BusError;
+use crate::emulator::runtime::model::Model;
+use crate::emulator::runtime::sgb::{self, PACKET_SIZE, Packet};
+
+use super::interrupts::{InterruptKind, Interrupts};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    // the buttons are wired up as a 2x4 matrix, this returns the row (false for the
+    // directions, true for the action buttons) and the bit of the column
+    fn position(self) -> (bool, u8) {
+        match self {
+            Button::Right => (false, 0),
+            Button::Left => (false, 1),
+            Button::Up => (false, 2),
+            Button::Down => (false, 3),
+            Button::A => (true, 0),
+            Button::B => (true, 1),
+            Button::Select => (true, 2),
+            Button::Start => (true, 3),
+        }
+    }
+}
+
+// a button press or release, to be applied once emulation reaches `cycle`. pinning
+// input to emulated time instead of whenever the message arrives keeps replays and
+// recordings deterministic.
+#[derive(Debug, Clone, Copy)]
+pub struct Input {
+    pub button: Button,
+    pub is_pressed: bool,
+    pub cycle: u64,
+}
+
+// the sgb side of the joypad register. packets are sent one bit at a time by pulling
+// P14 (a 0) or P15 (a 1) low, with both going high in between. pulling both low at once
+// starts a new packet.
+#[derive(Default)]
+struct SgbLink {
+    // bits received of the current packet, if one was started
+    bit: Option<usize>,
+    packet: Packet,
+    received: VecDeque<Packet>,
+    // the select lines as of the last write
+    last_select: u8,
+    // set up by MLT_REQ. with more than one player, reading with both rows deselected
+    // returns which one is currently selected.
+    players: u8,
+    player: u8,
+}
+
+impl SgbLink {
+    fn write(&mut self, select: u8) {
+        let previous = std::mem::replace(&mut self.last_select, select);
+
+        // pulling P15 low moves on to the next player
+        if previous & 0b10 != 0 && select & 0b10 == 0 && self.players > 1 {
+            self.player = (self.player + 1) % self.players;
+        }
+
+        match select {
+            0b00 => {
+                self.bit = Some(0);
+                self.packet = [0; PACKET_SIZE];
+            }
+            // only the first write of a pulse counts
+            _ if previous != 0b11 => (),
+            0b01 | 0b10 => self.receive_bit(select == 0b01),
+            _ => (),
+        }
+    }
+
+    // P14 low sends a 0, P15 low a 1
+    fn receive_bit(&mut self, is_set: bool) {
+        let Some(bit) = self.bit else {
+            return;
+        };
+
+        // the packet is followed by a 0 stop bit
+        if bit == PACKET_SIZE * 8 {
+            self.bit = None;
+            if !is_set {
+                self.complete_packet();
+            }
+            return;
+        }
+
+        if is_set {
+            self.packet[bit / 8] |= 1 << (bit % 8);
+        }
+        self.bit = Some(bit + 1);
+    }
+
+    fn complete_packet(&mut self) {
+        if sgb::command_of(&self.packet) == sgb::MLT_REQ {
+            self.players = match self.packet[1] & 0b11 {
+                1 => 2,
+                3 => 4,
+                _ => 1,
+            };
+            self.player = 0;
+        }
+        self.received.push_back(self.packet);
+    }
+}
+
+#[derive(Default)]
+pub struct Joypad {
+    // whether P14 / P15 are pulled low, which selects that row of buttons
+    select_directions: bool,
+    select_actions: bool,
+    // pressed buttons, one bit per column, active high
+    directions: u8,
+    actions: u8,
+    queue: VecDeque<Input>,
+    cycles: u64,
+    sgb: Option<SgbLink>,
+}
+
+impl Joypad {
+    pub fn new(model: Model) -> Self {
+        Self {
+            sgb: (model == Model::Sgb).then(SgbLink::default),
+            ..Self::default()
+        }
+    }
+
+    // packets the game sent to the sgb, oldest first
+    pub fn take_sgb_packet(&mut self) -> Option<Packet> {
+        self.sgb.as_mut()?.received.pop_front()
+    }
+
+    // inputs timestamped in the past are applied on the next tick
+    pub fn queue(&mut self, input: Input) {
+        let index = self
+            .queue
+            .partition_point(|queued| queued.cycle <= input.cycle);
+        self.queue.insert(index, input);
+    }
+
+    // lower nibble of the register as seen by the cpu, active low
+    fn lines(&self) -> u8 {
+        if let Some(sgb) = &self.sgb
+            && sgb.players > 1
+        {
+            if !self.select_directions && !self.select_actions {
+                return 0x0F - sgb.player;
+            }
+            // only the first player is hooked up
+            if sgb.player != 0 {
+                return 0x0F;
+            }
+        }
+
+        let mut pressed = 0;
+        if self.select_directions {
+            pressed |= self.directions;
+        }
+        if self.select_actions {
+            pressed |= self.actions;
+        }
+        !pressed & 0x0F
+    }
+
+    // runs `change` and requests the joypad interrupt if any line went from high to low
+    fn update(&mut self, interrupts: &mut Interrupts, change: impl FnOnce(&mut Self)) {
+        let before = self.lines();
+        change(self);
+        if before & !self.lines() != 0 {
+            interrupts.get_mut(InterruptKind::Joypad).is_requested = true;
+        }
+    }
+
+    fn apply(&mut self, input: Input) {
+        let (is_action, bit) = input.button.position();
+        let row = if is_action {
+            &mut self.actions
+        } else {
+            &mut self.directions
+        };
+        if input.is_pressed {
+            *row |= 1 << bit;
+        } else {
+            *row &= !(1 << bit);
+        }
+    }
+
+    fn set_select(&mut self, content: u8) {
+        self.select_directions = content & 0b0001_0000 == 0;
+        self.select_actions = content & 0b0010_0000 == 0;
+    }
+}
+
+impl Device for Joypad {
+    fn read(&self, addr: u16) -> Result<u8, BusError> {
+        if addr != 0xFF00 {
+            return Err(BusError::Unimplemented(addr));
+        }
+        let mut value = 0b1100_0000 | self.lines();
+        if !self.select_directions {
+            value |= 0b0001_0000;
+        }
+        if !self.select_actions {
+            value |= 0b0010_0000;
+        }
+        Ok(value)
+    }
+
+    fn write(
+        &mut self,
+        addr: u16,
+        content: u8,
+        interrupts: &mut Interrupts,
+    ) -> Result<(), BusError> {
+        if addr != 0xFF00 {
+            return Err(BusError::Unimplemented(addr));
+        }
+        self.update(interrupts, |joypad| joypad.set_select(content));
+        if let Some(sgb) = &mut self.sgb {
+            sgb.write((content >> 4) & 0b11);
+        }
+        Ok(())
+    }
+
+    fn poke(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        if addr != 0xFF00 {
+            return Err(BusError::Unimplemented(addr));
+        }
+        self.set_select(content);
+        Ok(())
+    }
+
+    fn tick(&mut self, cycles: u8, interrupts: &mut Interrupts) {
+        self.cycles += cycles as u64;
+        while let Some(input) = self.queue.front()
+            && input.cycle <= self.cycles
+        {
+            let input = self.queue.pop_front().unwrap();
+            self.update(interrupts, |joypad| joypad.apply(input));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(button: Button, is_pressed: bool, cycle: u64) -> Input {
+        Input {
+            button,
+            is_pressed,
+            cycle,
+        }
+    }
+
+    fn is_requested(interrupts: &Interrupts) -> bool {
+        interrupts.get(InterruptKind::Joypad).is_requested
+    }
+
+    #[test]
+    fn reads_back_the_selected_rows() {
+        let mut interrupts = Interrupts::default();
+        let mut joypad = Joypad::new(Model::Dmg);
+        joypad.queue(input(Button::Right, true, 0));
+        joypad.queue(input(Button::Start, true, 0));
+        joypad.tick(4, &mut interrupts);
+
+        // P14 low selects the directions, P15 low the action buttons
+        for (select, expected) in [
+            (0x20, 0b1110_1110),
+            (0x10, 0b1101_0111),
+            (0x00, 0b1100_0110),
+            (0x30, 0b1111_1111),
+        ] {
+            joypad.write(0xFF00, select, &mut interrupts).unwrap();
+            assert_eq!(joypad.read(0xFF00).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn interrupts_only_when_a_line_goes_low() {
+        let mut interrupts = Interrupts::default();
+        let mut joypad = Joypad::new(Model::Dmg);
+
+        // nothing shows up on the lines while neither row is selected
+        joypad.write(0xFF00, 0x30, &mut interrupts).unwrap();
+        joypad.queue(input(Button::Right, true, 0));
+        joypad.tick(4, &mut interrupts);
+        assert!(!is_requested(&interrupts));
+
+        // selecting a row with a button held pulls its line low
+        joypad.write(0xFF00, 0x20, &mut interrupts).unwrap();
+        assert!(is_requested(&interrupts));
+
+        // letting go raises it again, which doesn't count
+        interrupts.get_mut(InterruptKind::Joypad).is_requested = false;
+        joypad.queue(input(Button::Right, false, 8));
+        joypad.tick(4, &mut interrupts);
+        assert!(!is_requested(&interrupts));
+
+        joypad.queue(input(Button::Left, true, 12));
+        joypad.tick(4, &mut interrupts);
+        assert!(is_requested(&interrupts));
+    }
+
+    #[test]
+    fn inputs_apply_in_cycle_order() {
+        let mut interrupts = Interrupts::default();
+        let mut joypad = Joypad::new(Model::Dmg);
+        joypad.write(0xFF00, 0x10, &mut interrupts).unwrap();
+
+        // queued out of order, and a press and release on the same cycle in that order
+        joypad.queue(input(Button::A, true, 40));
+        joypad.queue(input(Button::B, true, 20));
+        joypad.queue(input(Button::Start, true, 60));
+        joypad.queue(input(Button::Start, false, 60));
+
+        joypad.tick(16, &mut interrupts);
+        assert_eq!(joypad.read(0xFF00).unwrap() & 0x0F, 0b1111);
+        joypad.tick(4, &mut interrupts);
+        assert_eq!(joypad.read(0xFF00).unwrap() & 0x0F, 0b1101);
+        joypad.tick(20, &mut interrupts);
+        assert_eq!(joypad.read(0xFF00).unwrap() & 0x0F, 0b1100);
+        joypad.tick(20, &mut interrupts);
+        assert_eq!(joypad.read(0xFF00).unwrap() & 0x0F, 0b1100);
+
+        // input stamped in the past goes in on the next tick
+        joypad.queue(input(Button::Select, true, 0));
+        joypad.tick(4, &mut interrupts);
+        assert_eq!(joypad.read(0xFF00).unwrap() & 0x0F, 0b1000);
+    }
+}
@@ -1,6 +1,7 @@
+use crate::emulator::runtime::bus::device::Device;
 use crate::emulator::runtime::bus::error::BusError;
 
-use super::interrupts::Interrupt;
+use super::interrupts::{Interrupt, InterruptKind, Interrupts};
 
 #[derive(Default)]
 pub struct Serial {
@@ -9,33 +10,47 @@ pub struct Serial {
     pub outgoing: Vec<u8>,
 }
 
-impl Serial {
-    pub fn read(&self, addr: u16) -> Result<u8, BusError> {
+impl Device for Serial {
+    fn read(&self, addr: u16) -> Result<u8, BusError> {
         if addr == 0xFF01 {
             return Ok(self.content);
         }
         Ok(self.control.to_byte())
     }
 
-    pub fn write(
+    fn write(
         &mut self,
         addr: u16,
         content: u8,
-        interrupt: &mut Interrupt,
+        interrupts: &mut Interrupts,
     ) -> Result<(), BusError> {
         if addr == 0xFF01 {
             self.content = content;
+            return Ok(());
         }
         self.control.set(content);
         if self.control.enable {
-            self.transfer(interrupt);
+            self.transfer(interrupts.get_mut(InterruptKind::Serial));
         }
         Ok(())
     }
 
+    fn poke(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        if addr == 0xFF01 {
+            self.content = content;
+        } else {
+            self.control.set(content);
+        }
+        Ok(())
+    }
+}
+
+impl Serial {
     fn transfer(&mut self, interrupt: &mut Interrupt) {
+        // with the external clock the other game boy drives the transfer. there's no
+        // link cable, so it stays pending forever like it does on hardware
         if !self.control.should_use_internal_clock {
-            unimplemented!("use of external serial transfer has not been implemented yet");
+            return;
         }
         self.outgoing.push(self.content);
         self.control.enable = false;
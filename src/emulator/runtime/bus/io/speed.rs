@@ -0,0 +1,85 @@
+use crate::emulator::runtime::bus::device::Device;
+use crate::emulator::runtime::bus::error::BusError;
+use crate::emulator::runtime::bus::io::interrupts::Interrupts;
+use crate::emulator::runtime::model::Model;
+
+// KEY1, the cgb speed switch. writing bit 0 only arms it, the switch itself happens on
+// the next STOP, which the bus carries out through `switch`. reads 0xFF on dmg.
+pub struct SpeedSwitch {
+    model: Model,
+    is_double_speed: bool,
+    is_armed: bool,
+}
+
+impl SpeedSwitch {
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            is_double_speed: false,
+            is_armed: false,
+        }
+    }
+
+    // toggles the speed if a switch was armed, returning the speed that's now in effect
+    pub fn switch(&mut self) -> Option<bool> {
+        if !self.is_armed {
+            return None;
+        }
+        self.is_armed = false;
+        self.is_double_speed = !self.is_double_speed;
+        Some(self.is_double_speed)
+    }
+}
+
+impl Device for SpeedSwitch {
+    fn read(&self, addr: u16) -> Result<u8, BusError> {
+        match addr {
+            0xFF4D if !self.model.is_cgb() => Ok(0xFF),
+            0xFF4D => Ok(((self.is_double_speed as u8) << 7) | 0b0111_1110 | self.is_armed as u8),
+            _ => Err(BusError::Unimplemented(addr)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        addr: u16,
+        content: u8,
+        _interrupts: &mut Interrupts,
+    ) -> Result<(), BusError> {
+        match addr {
+            0xFF4D if !self.model.is_cgb() => (),
+            0xFF4D => self.is_armed = content & 1 != 0,
+            _ => return Err(BusError::Unimplemented(addr)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switching_needs_to_be_armed() {
+        let mut key1 = SpeedSwitch::new(Model::Cgb);
+        assert_eq!(key1.read(0xFF4D).unwrap(), 0x7E);
+        assert_eq!(key1.switch(), None);
+
+        key1.write(0xFF4D, 0x01, &mut Interrupts::default())
+            .unwrap();
+        assert_eq!(key1.read(0xFF4D).unwrap(), 0x7F);
+        assert_eq!(key1.switch(), Some(true));
+        // the switch disarms itself
+        assert_eq!(key1.read(0xFF4D).unwrap(), 0xFE);
+        assert_eq!(key1.switch(), None);
+    }
+
+    #[test]
+    fn dmg_games_have_no_speed_switch() {
+        let mut key1 = SpeedSwitch::new(Model::CgbCompat);
+        key1.write(0xFF4D, 0x01, &mut Interrupts::default())
+            .unwrap();
+        assert_eq!(key1.read(0xFF4D).unwrap(), 0xFF);
+        assert_eq!(key1.switch(), None);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::emulator::runtime::bus::device::Device;
+use crate::emulator::runtime::bus::error::BusError;
+
+use super::interrupts::{InterruptKind, Interrupts};
+
+// the timer isn't clocked on its own, it watches a single bit of the 16-bit internal
+// divider (whose upper byte is DIV) and increments TIMA whenever that bit falls from 1
+// to 0. everything odd about it (incrementing on DIV writes, incrementing on TAC writes)
+// falls out of modelling that falling edge detector instead of counting cycles.
+#[derive(Default)]
+pub struct Timer {
+    divider: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    overflow: Overflow,
+}
+
+// after TIMA overflows it reads 0 for one m-cycle before being reloaded from TMA and
+// requesting the interrupt. writes behave differently during each of these cycles.
+#[derive(Default, Clone, Copy, PartialEq)]
+enum Overflow {
+    #[default]
+    None,
+    // TIMA overflowed and still reads 0, a write to it cancels the reload
+    Pending,
+    // TIMA was just reloaded, writes to it are ignored and writes to TMA go through to it
+    Reloading,
+}
+
+// divider bit selected by the lower two bits of TAC
+const TAC_BITS: [u16; 4] = [9, 3, 5, 7];
+
+impl Timer {
+    // the whole internal divider, which the apu's frame sequencer runs off as well
+    pub fn divider(&self) -> u16 {
+        self.divider
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.tac & 0b100 != 0
+    }
+
+    // output of the and gate between the selected divider bit and the enable bit,
+    // TIMA increments on its falling edge
+    fn signal(&self) -> bool {
+        let bit = TAC_BITS[(self.tac & 0b11) as usize];
+        self.is_enabled() && self.divider & (1 << bit) != 0
+    }
+
+    fn increment(&mut self) {
+        let (tima, has_overflown) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        if has_overflown {
+            self.overflow = Overflow::Pending;
+        }
+    }
+
+    // runs a single m-cycle
+    fn step(&mut self, interrupts: &mut Interrupts) {
+        match self.overflow {
+            Overflow::Pending => {
+                self.tima = self.tma;
+                interrupts.get_mut(InterruptKind::Timer).is_requested = true;
+                self.overflow = Overflow::Reloading;
+            }
+            Overflow::Reloading => self.overflow = Overflow::None,
+            Overflow::None => (),
+        }
+
+        let was_high = self.signal();
+        self.divider = self.divider.wrapping_add(4);
+        if was_high && !self.signal() {
+            self.increment();
+        }
+    }
+}
+
+impl Device for Timer {
+    fn read(&self, addr: u16) -> Result<u8, BusError> {
+        match addr {
+            0xFF04 => Ok((self.divider >> 8) as u8),
+            0xFF05 => Ok(self.tima),
+            0xFF06 => Ok(self.tma),
+            0xFF07 => Ok(0b1111_1000 | self.tac),
+            _ => Err(BusError::Unimplemented(addr)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        addr: u16,
+        content: u8,
+        _interrupts: &mut Interrupts,
+    ) -> Result<(), BusError> {
+        let was_high = self.signal();
+
+        match addr {
+            // any write resets the whole divider, which can look like a falling edge
+            0xFF04 => self.divider = 0,
+            0xFF05 => match self.overflow {
+                Overflow::Pending => {
+                    self.overflow = Overflow::None;
+                    self.tima = content;
+                }
+                Overflow::Reloading => (),
+                Overflow::None => self.tima = content,
+            },
+            0xFF06 => {
+                self.tma = content;
+                if self.overflow == Overflow::Reloading {
+                    self.tima = content;
+                }
+            }
+            // disabling the timer or switching to a bit that's low can look like a
+            // falling edge as well (at least on dmg)
+            0xFF07 => self.tac = content & 0b111,
+            _ => return Err(BusError::Unimplemented(addr)),
+        }
+
+        if was_high && !self.signal() {
+            self.increment();
+        }
+        Ok(())
+    }
+
+    fn poke(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        match addr {
+            0xFF04 => self.divider = (content as u16) << 8,
+            0xFF05 => self.tima = content,
+            0xFF06 => self.tma = content,
+            0xFF07 => self.tac = content & 0b111,
+            _ => return Err(BusError::Unimplemented(addr)),
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, cycles: u8, interrupts: &mut Interrupts) {
+        for _ in 0..cycles / 4 {
+            self.step(interrupts);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // TIMA counting on bit 3 of the divider, so once every 16 t-cycles
+    fn timer_at_16_cycles(interrupts: &mut Interrupts) -> Timer {
+        let mut timer = Timer::default();
+        timer.write(0xFF07, 0b101, interrupts).unwrap();
+        timer
+    }
+
+    fn is_requested(interrupts: &Interrupts) -> bool {
+        interrupts.get(InterruptKind::Timer).is_requested
+    }
+
+    #[test]
+    fn tima_increments_when_the_selected_bit_falls() {
+        let mut interrupts = Interrupts::default();
+        let mut timer = timer_at_16_cycles(&mut interrupts);
+
+        timer.tick(12, &mut interrupts);
+        assert_eq!(timer.read(0xFF05).unwrap(), 0);
+        timer.tick(4, &mut interrupts);
+        assert_eq!(timer.read(0xFF05).unwrap(), 1);
+    }
+
+    #[test]
+    fn resetting_div_while_the_bit_is_high_increments_tima() {
+        let mut interrupts = Interrupts::default();
+        let mut timer = timer_at_16_cycles(&mut interrupts);
+        timer.tick(8, &mut interrupts);
+
+        timer.write(0xFF04, 0x12, &mut interrupts).unwrap();
+        assert_eq!(timer.divider(), 0);
+        assert_eq!(timer.read(0xFF05).unwrap(), 1);
+    }
+
+    #[test]
+    fn resetting_div_while_the_bit_is_low_does_nothing() {
+        let mut interrupts = Interrupts::default();
+        let mut timer = timer_at_16_cycles(&mut interrupts);
+        timer.tick(4, &mut interrupts);
+
+        timer.write(0xFF04, 0, &mut interrupts).unwrap();
+        assert_eq!(timer.read(0xFF05).unwrap(), 0);
+    }
+
+    #[test]
+    fn tac_writes_that_drop_the_signal_increment_tima() {
+        let mut interrupts = Interrupts::default();
+
+        // disabling the timer
+        let mut timer = timer_at_16_cycles(&mut interrupts);
+        timer.tick(8, &mut interrupts);
+        timer.write(0xFF07, 0b001, &mut interrupts).unwrap();
+        assert_eq!(timer.read(0xFF05).unwrap(), 1);
+
+        // switching from bit 3, which is high, to bit 5, which is low
+        let mut timer = timer_at_16_cycles(&mut interrupts);
+        timer.tick(8, &mut interrupts);
+        timer.write(0xFF07, 0b110, &mut interrupts).unwrap();
+        assert_eq!(timer.read(0xFF05).unwrap(), 1);
+    }
+
+    fn overflowing_timer(interrupts: &mut Interrupts) -> Timer {
+        let mut timer = timer_at_16_cycles(interrupts);
+        timer.write(0xFF05, 0xFF, interrupts).unwrap();
+        timer.write(0xFF06, 0x42, interrupts).unwrap();
+        timer.tick(16, interrupts);
+        timer
+    }
+
+    #[test]
+    fn overflow_reads_0_for_an_m_cycle_before_reloading() {
+        let mut interrupts = Interrupts::default();
+        let mut timer = overflowing_timer(&mut interrupts);
+        assert_eq!(timer.read(0xFF05).unwrap(), 0);
+        assert!(!is_requested(&interrupts));
+
+        timer.tick(4, &mut interrupts);
+        assert_eq!(timer.read(0xFF05).unwrap(), 0x42);
+        assert!(is_requested(&interrupts));
+    }
+
+    #[test]
+    fn writing_tima_before_the_reload_cancels_it() {
+        let mut interrupts = Interrupts::default();
+        let mut timer = overflowing_timer(&mut interrupts);
+
+        timer.write(0xFF05, 0x10, &mut interrupts).unwrap();
+        timer.tick(4, &mut interrupts);
+        assert_eq!(timer.read(0xFF05).unwrap(), 0x10);
+        assert!(!is_requested(&interrupts));
+    }
+
+    #[test]
+    fn tima_ignores_writes_while_reloading_but_tma_goes_through() {
+        let mut interrupts = Interrupts::default();
+        let mut timer = overflowing_timer(&mut interrupts);
+        timer.tick(4, &mut interrupts);
+
+        timer.write(0xFF05, 0x10, &mut interrupts).unwrap();
+        assert_eq!(timer.read(0xFF05).unwrap(), 0x42);
+        timer.write(0xFF06, 0x77, &mut interrupts).unwrap();
+        assert_eq!(timer.read(0xFF05).unwrap(), 0x77);
+
+        // and it's all back to normal an m-cycle later
+        timer.tick(4, &mut interrupts);
+        timer.write(0xFF05, 0x10, &mut interrupts).unwrap();
+        assert_eq!(timer.read(0xFF05).unwrap(), 0x10);
+    }
+}
@@ -0,0 +1,30 @@
+// observers get to see every access going over the bus without the bus (or the cpu)
+// knowing what they do with it. this is the building block for debugger features like
+// watchpoints, access heatmaps or code/data logging.
+//
+// they're shared with whoever attached them (usually a frontend), so they only get a
+// shared reference and have to deal with their own interior mutability.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    // the cpu reading an opcode, as opposed to reading operands or data
+    Fetch,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub addr: u16,
+    pub value: u8,
+    // address of the instruction that caused the access
+    pub pc: u16,
+    pub bank: u16,
+    // t-cycles since power on, up to the end of the m-cycle the access happened in
+    pub cycle: u64,
+}
+
+pub trait Observer: Send + Sync {
+    fn on_access(&self, access: &Access);
+}
@@ -0,0 +1,113 @@
+use crate::emulator::runtime::bus::device::Device;
+use crate::emulator::runtime::bus::error::BusError;
+use crate::emulator::runtime::bus::io::interrupts::Interrupts;
+use crate::emulator::runtime::model::Model;
+
+// work ram at 0xC000-0xDFFF, mirrored at 0xE000-0xFDFF. the cgb has 8 banks of 4 KiB,
+// the upper half switches between banks 1-7 through SVBK.
+pub struct Wram {
+    model: Model,
+    banks: Box<[u8]>,
+    svbk: u8,
+}
+
+const BANK_SIZE: usize = 0x1000;
+
+impl Wram {
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            banks: vec![0; 8 * BANK_SIZE].into_boxed_slice(),
+            svbk: 0,
+        }
+    }
+
+    // the bank in the upper half, selecting bank 0 gets bank 1 instead
+    fn bank(&self) -> u16 {
+        if self.model.is_cgb() {
+            (self.svbk as u16).max(1)
+        } else {
+            1
+        }
+    }
+
+    fn is_upper_half(addr: u16) -> bool {
+        matches!(addr, 0xD000..=0xDFFF | 0xF000..=0xFDFF)
+    }
+
+    fn offset(bank: u16, addr: u16) -> usize {
+        let addr = (addr - 0xC000) as usize % 0x2000;
+        if addr < BANK_SIZE {
+            addr
+        } else {
+            bank as usize * BANK_SIZE + addr - BANK_SIZE
+        }
+    }
+
+    fn has_bank(&self, bank: u16, addr: u16) -> bool {
+        match addr {
+            _ if !Self::is_upper_half(addr) => bank == 0,
+            _ if self.model.is_cgb() => (1..8).contains(&bank),
+            _ => bank == 1,
+        }
+    }
+}
+
+impl Device for Wram {
+    fn read(&self, addr: u16) -> Result<u8, BusError> {
+        match addr {
+            0xC000..=0xFDFF => Ok(self.banks[Self::offset(self.bank(), addr)]),
+            0xFF70 if self.model.is_cgb() => Ok(0b1111_1000 | self.svbk),
+            0xFF70 => Ok(0xFF),
+            _ => Err(BusError::Unimplemented(addr)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        addr: u16,
+        content: u8,
+        _interrupts: &mut Interrupts,
+    ) -> Result<(), BusError> {
+        self.poke(addr, content)
+    }
+
+    fn poke(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        match addr {
+            0xC000..=0xFDFF => self.banks[Self::offset(self.bank(), addr)] = content,
+            0xFF70 if self.model.is_cgb() => self.svbk = content & 0b111,
+            0xFF70 => (),
+            _ => return Err(BusError::Unimplemented(addr)),
+        }
+        Ok(())
+    }
+
+    fn bank_of(&self, addr: u16) -> u16 {
+        if Self::is_upper_half(addr) {
+            self.bank()
+        } else {
+            0
+        }
+    }
+
+    fn peek_bank(&self, bank: u16, addr: u16) -> Result<u8, BusError> {
+        match addr {
+            0xC000..=0xFDFF if self.has_bank(bank, addr) => {
+                Ok(self.banks[Self::offset(bank, addr)])
+            }
+            _ if bank == 0 => self.peek(addr),
+            _ => Err(BusError::NoSuchBank(bank, addr)),
+        }
+    }
+
+    fn poke_bank(&mut self, bank: u16, addr: u16, content: u8) -> Result<(), BusError> {
+        match addr {
+            0xC000..=0xFDFF if self.has_bank(bank, addr) => {
+                self.banks[Self::offset(bank, addr)] = content;
+                Ok(())
+            }
+            _ if bank == 0 => self.poke(addr, content),
+            _ => Err(BusError::NoSuchBank(bank, addr)),
+        }
+    }
+}
@@ -5,6 +5,8 @@ use crate::emulator::util::Register;
 use crate::emulator::util::RegisterPair;
 use crate::emulator::util::get_register_pair_by_code;
 
+mod timing;
+
 pub struct Flags {
     pub zero: bool,
     pub subtraction: bool,
@@ -29,6 +31,9 @@ pub struct CPU {
 
     pub is_halting: bool,
 
+    // set by conditional jumps, calls and returns that were taken, which costs extra cycles
+    pub is_branch_taken: bool,
+
     pub should_trace_log: bool,
 }
 
@@ -52,6 +57,7 @@ impl CPU {
             pc: 0x0100,
             ie_enable_delay: false,
             is_halting: false,
+            is_branch_taken: false,
             should_trace_log,
         }
     }
@@ -61,24 +67,30 @@ impl CPU {
         *self = CPU::new(self.should_trace_log);
     }
 
-    pub fn step(&mut self, bus: &mut Bus) {
+    // executes a single instruction and returns how many t-cycles it took
+    pub fn step(&mut self, bus: &mut Bus) -> u8 {
         if self.ie_enable_delay {
             self.ie_enable_delay = false;
             bus.interrupts.ime = true;
         }
 
         if self.is_halting {
-            return;
+            // any pending interrupt wakes the cpu up, even if IME won't let it be serviced
+            if !bus.interrupts.has_pending() {
+                return 4;
+            }
+            self.is_halting = false;
         }
 
-        self.handle_interrupts(bus);
+        let interrupt_cycles = self.handle_interrupts(bus);
 
         // fetch
-        let opcode = match bus.read_byte(self.pc) {
+        self.is_branch_taken = false;
+        let opcode = match bus.fetch_byte(self.pc) {
             Ok(byte) => byte,
             Err(e) => {
                 eprintln!("{}", e);
-                return;
+                return 4 + interrupt_cycles;
             }
         };
 
@@ -93,15 +105,18 @@ impl CPU {
             0o373 => {
                 instruction::ei::ei(self);
             }
-            0o166 | 0o20 => {
+            0o166 => {
                 instruction::halt::halt(self);
             }
+            0o20 => {
+                instruction::halt::stop(self, bus);
+            }
             0o03 | 0o13 | 0o23 | 0o33 | 0o43 | 0o53 | 0o63 | 0o73 => {
                 let pair = get_register_pair_by_code(opcode >> 4);
-                if (opcode >> 3) & 0 == 1 {
-                    instruction::inc::r16(self, pair);
+                if (opcode >> 3) & 1 == 0 {
+                    instruction::inc::r16(self, bus, pair);
                 } else {
-                    instruction::dec::r16(self, pair);
+                    instruction::dec::r16(self, bus, pair);
                 }
             }
             0o04 | 0o14 | 0o24 | 0o34 | 0o44 | 0o54 | 0o64 | 0o74 => {
@@ -113,7 +128,7 @@ impl CPU {
             0o06 | 0o16 | 0o26 | 0o36 | 0o46 | 0o56 | 0o66 | 0o76 => {
                 instruction::ld::r8_n8(self, bus, opcode);
             }
-            0o100..=0o175 | 0o167..=0o177 => {
+            0o100..=0o177 => {
                 instruction::ld::r8_r8(self, bus, opcode);
             }
             0o01 | 0o21 | 0o41 | 0o61 => {
@@ -205,13 +220,20 @@ impl CPU {
             }
             0o323 | 0o333 | 0o343 | 0o353 | 0o344 | 0o354 | 0o364 | 0o374 | 0o335 | 0o355
             | 0o375 => {
-                return;
+                return 4 + interrupt_cycles;
             }
             _ => {
                 unimplemented!("Opcode {:02X} not implemented yet", opcode);
             }
         }
 
+        let mut m_cycles = timing::M_CYCLES[opcode as usize];
+        if let Some(penalty) = timing::branch_penalty(opcode)
+            && self.is_branch_taken
+        {
+            m_cycles += penalty;
+        }
+
         if self.should_trace_log {
             if let Some(disasm) = disassemble::disassemble(&*bus, self.pc) {
                 println!(
@@ -235,6 +257,8 @@ impl CPU {
                 println!("{:04X}: <undisassembled>", self.pc);
             }
         }
+
+        m_cycles * 4 + interrupt_cycles
     }
 
     pub fn get_register(&self, bus: &mut Bus, register: Register) -> u8 {
@@ -310,10 +334,11 @@ impl CPU {
         }
     }
 
-    fn handle_interrupts(&mut self, bus: &mut Bus) {
+    // dispatches the highest priority pending interrupt and returns the t-cycles spent doing so
+    fn handle_interrupts(&mut self, bus: &mut Bus) -> u8 {
         let interrupts = &mut bus.interrupts;
         if !interrupts.ime {
-            return;
+            return 0;
         };
 
         for (idx, interrupt) in interrupts.registers.iter_mut().enumerate() {
@@ -325,12 +350,101 @@ impl CPU {
 
                 if let Err(e) = bus.push_word(&mut self.sp, self.pc) {
                     eprintln!("Failed to push PC during interrupt: {}", e);
-                    return;
+                    return 0;
                 }
 
-                self.pc = idx as u16 + 0x40;
-                break;
+                self.pc = 0x40 + idx as u16 * 8;
+                return 20;
             }
         }
+
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::runtime::model::Model;
+
+    fn run(code: &[u8], is_zero: bool) -> (u8, u16) {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x100 + code.len()].copy_from_slice(code);
+        let mut bus = Bus::from_cartridge_rom(rom, Model::default()).unwrap();
+        let mut cpu = CPU::new(false);
+        cpu.flags.zero = is_zero;
+        let cycles = cpu.step(&mut bus);
+        (cycles, cpu.pc)
+    }
+
+    fn stop(model: Model, is_armed: bool) -> (CPU, Bus) {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100] = 0x10;
+        let mut bus = Bus::from_cartridge_rom(rom, model).unwrap();
+        if is_armed {
+            bus.write_byte(0xFF4D, 0x01).unwrap();
+        }
+        bus.tick(0);
+        bus.tick(255);
+        bus.tick(255);
+        let mut cpu = CPU::new(false);
+        let cycles = cpu.step(&mut bus);
+        bus.tick(cycles);
+        (cpu, bus)
+    }
+
+    #[test]
+    fn taken_jr_to_the_next_instruction_costs_the_penalty() {
+        // JR NZ, +0 lands where it would have anyway
+        assert_eq!(run(&[0x20, 0x00], false), (12, 0x102));
+        assert_eq!(run(&[0x20, 0x00], true), (8, 0x102));
+    }
+
+    #[test]
+    fn timer_overflow_wakes_halt_and_jumps_to_its_vector() {
+        // TAC = 0b101, TIMA = 0xF0, IE = timer, EI, HALT, then a NOP to return to
+        let code = [
+            0x3E, 0x05, 0xE0, 0x07, 0x3E, 0xF0, 0xE0, 0x05, 0x3E, 0x04, 0xE0, 0xFF, 0xFB, 0x76,
+            0x00,
+        ];
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x100 + code.len()].copy_from_slice(&code);
+        // the handler spins in place with JR -2
+        rom[0x50..0x52].copy_from_slice(&[0x18, 0xFE]);
+        let mut bus = Bus::from_cartridge_rom(rom, Model::default()).unwrap();
+        let mut cpu = CPU::new(false);
+        for _ in 0..100 {
+            let cycles = cpu.step(&mut bus);
+            bus.tick(cycles);
+        }
+
+        assert_eq!(cpu.pc, 0x50);
+        assert!(!cpu.is_halting);
+        // the return address is the instruction after HALT
+        assert_eq!(cpu.sp, 0xFFFC);
+        assert_eq!(bus.peek(0xFFFC).unwrap(), 0x0E);
+        assert_eq!(bus.peek(0xFFFD).unwrap(), 0x01);
+    }
+
+    #[test]
+    fn stop_skips_its_second_byte_and_resets_div() {
+        let (cpu, bus) = stop(Model::Dmg, false);
+        assert_eq!(cpu.pc, 0x102);
+        assert!(cpu.is_halting);
+        assert_eq!(bus.peek(0xFF04).unwrap(), 0);
+    }
+
+    #[test]
+    fn stop_switches_speed_once_armed() {
+        let (cpu, bus) = stop(Model::Cgb, true);
+        assert_eq!(cpu.pc, 0x102);
+        assert!(!cpu.is_halting);
+        assert!(bus.is_double_speed());
+
+        // without arming it first, it's just a halt
+        let (cpu, bus) = stop(Model::Cgb, false);
+        assert_eq!(cpu.pc, 0x102);
+        assert!(cpu.is_halting);
+        assert!(!bus.is_double_speed());
     }
 }
@@ -46,7 +46,7 @@ impl fmt::Display for Operand {
 
 pub fn disassemble(bus: &bus::Bus, addr: u16) -> Option<Disasm> {
     let opcode = bus
-        .read_byte(addr)
+        .peek(addr)
         .unwrap_or_else(|e| panic!("Tried to disassemble invalid address {:04X} - {}", addr, e));
     match opcode {
         0x00 => Some(Disasm {
@@ -62,7 +62,7 @@ pub fn disassemble(bus: &bus::Bus, addr: u16) -> Option<Disasm> {
         0o166 | 0o20 => instruction::halt::halt_disasm(bus, addr, opcode),
         0o03 | 0o13 | 0o23 | 0o33 | 0o43 | 0o53 | 0o63 | 0o73 => {
             let pair = get_register_pair_by_code(opcode >> 4);
-            if (opcode >> 3) & 0 == 1 {
+            if (opcode >> 3) & 1 == 0 {
                 instruction::inc::r16_disasm(bus, addr, opcode, pair)
             } else {
                 instruction::dec::r16_disasm(bus, addr, opcode, pair)
@@ -77,7 +77,7 @@ pub fn disassemble(bus: &bus::Bus, addr: u16) -> Option<Disasm> {
         0o06 | 0o16 | 0o26 | 0o36 | 0o46 | 0o56 | 0o66 | 0o76 => {
             instruction::ld::r8_n8_disasm(bus, addr, opcode)
         }
-        0o100..=0o175 | 0o167..=0o177 => instruction::ld::r8_r8_disasm(bus, addr, opcode),
+        0o100..=0o177 => instruction::ld::r8_r8_disasm(bus, addr, opcode),
         0o01 | 0o21 | 0o41 | 0o61 => instruction::ld::r16_n16_disasm(bus, addr, opcode),
         0o02 | 0o22 => instruction::ld::addr_of_r16_a_disasm(bus, addr, opcode),
         0o12 | 0o32 => instruction::ld::a_addr_of_r16_disasm(bus, addr, opcode),
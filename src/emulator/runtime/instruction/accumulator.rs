@@ -31,7 +31,7 @@ pub fn daa(cpu: &mut cpu::CPU, bus: &mut bus::Bus) {
             cpu.flags.carry = true;
             adjustment += 0x60;
         }
-        a = a + adjustment;
+        a += adjustment;
     }
 
     cpu.set_register(bus, util::Register::A, a);
@@ -79,7 +79,7 @@ pub fn r8_disasm(_bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
 }
 
 pub fn a_n8_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
-    let imm = bus.read_byte(addr + 1).unwrap();
+    let imm = bus.peek(addr + 1).unwrap();
 
     Some(Disasm {
         address: addr,
@@ -92,7 +92,7 @@ pub fn a_n8_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
 }
 
 pub fn sp_e8_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
-    let offset = bus.read_byte(addr + 1).unwrap() as i8;
+    let offset = bus.peek(addr + 1).unwrap() as i8;
 
     Some(Disasm {
         address: addr,
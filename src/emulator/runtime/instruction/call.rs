@@ -16,6 +16,7 @@ pub fn call(cpu: &mut cpu::CPU, bus: &mut bus::Bus, opcode: u8) {
     };
 
     if should_jump || opcode == 0o315 {
+        cpu.is_branch_taken = true;
         let _ = bus.push_word(&mut cpu.sp, cpu.pc + 3);
         cpu.pc = ((hi as u16) << 8) | lo as u16;
     } else {
@@ -24,7 +25,7 @@ pub fn call(cpu: &mut cpu::CPU, bus: &mut bus::Bus, opcode: u8) {
 }
 
 pub fn call_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
-    let target = bus.read_word(addr + 1).unwrap();
+    let target = bus.peek_word(addr + 1).unwrap();
 
     let instr = match opcode {
         0xCD => vec!["CALL".to_string(), "".to_string()],
@@ -1,5 +1,6 @@
 use crate::emulator::runtime::bus;
 use crate::emulator::runtime::disassemble::Operand;
+use crate::emulator::runtime::ppu::oam_bug::Corruption;
 use crate::emulator::runtime::{cpu, disassemble::Disasm};
 use crate::emulator::util;
 
@@ -20,8 +21,9 @@ pub fn r8(cpu: &mut cpu::CPU, bus: &mut bus::Bus, opcode: u8) {
     cpu.pc += 1;
 }
 
-pub fn r16(cpu: &mut cpu::CPU, pair: util::RegisterPair) {
+pub fn r16(cpu: &mut cpu::CPU, bus: &mut bus::Bus, pair: util::RegisterPair) {
     let current = cpu.get_register_pair(pair);
+    bus.trigger_oam_bug(current, Corruption::Write);
     let new = current.wrapping_sub(1);
 
     cpu.set_register_pair(pair, new);
@@ -3,6 +3,18 @@ use crate::emulator::runtime::{cpu, disassemble::Disasm};
 
 pub fn halt(cpu: &mut cpu::CPU) {
     cpu.is_halting = true;
+    cpu.pc += 1;
+}
+
+// STOP only does something useful on cgb, where it switches speeds. otherwise it's
+// treated like HALT. either way it's two bytes long, the second one being ignored, and
+// resets DIV.
+pub fn stop(cpu: &mut cpu::CPU, bus: &mut bus::Bus) {
+    cpu.pc += 2;
+    bus.reset_divider();
+    if !bus.switch_speed() {
+        cpu.is_halting = true;
+    }
 }
 
 pub(crate) fn halt_disasm(_bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
@@ -2,6 +2,7 @@ use crate::emulator::runtime::bus;
 use crate::emulator::runtime::cpu;
 use crate::emulator::runtime::disassemble::Disasm;
 use crate::emulator::runtime::disassemble::Operand;
+use crate::emulator::runtime::ppu::oam_bug::Corruption;
 use crate::emulator::util;
 
 pub fn r8(cpu: &mut cpu::CPU, bus: &mut bus::Bus, opcode: u8) {
@@ -21,8 +22,9 @@ pub fn r8(cpu: &mut cpu::CPU, bus: &mut bus::Bus, opcode: u8) {
     cpu.pc += 1;
 }
 
-pub fn r16(cpu: &mut cpu::CPU, pair: util::RegisterPair) {
+pub fn r16(cpu: &mut cpu::CPU, bus: &mut bus::Bus, pair: util::RegisterPair) {
     let current = cpu.get_register_pair(pair);
+    bus.trigger_oam_bug(current, Corruption::Write);
     let new = current.wrapping_add(1);
 
     cpu.set_register_pair(pair, new);
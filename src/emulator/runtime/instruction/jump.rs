@@ -15,6 +15,7 @@ pub fn a16(cpu: &mut cpu::CPU, bus: &mut bus::Bus, opcode: u8) {
     };
 
     if should_jump || opcode == 0o303 {
+        cpu.is_branch_taken = true;
         cpu.pc = target;
     } else {
         cpu.pc += 3;
@@ -39,6 +40,7 @@ pub fn e8(cpu: &mut cpu::CPU, bus: &mut bus::Bus, opcode: u8) {
     };
 
     if should_jump {
+        cpu.is_branch_taken = true;
         cpu.pc = target;
     } else {
         cpu.pc += 2;
@@ -51,7 +53,7 @@ pub fn hl(cpu: &mut cpu::CPU) {
 }
 
 pub fn a16_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
-    let target = bus.read_word(addr + 1).unwrap();
+    let target = bus.peek_word(addr + 1).unwrap();
 
     let instr = match opcode {
         0xC3 => vec!["JP".to_string(), "".to_string()],
@@ -80,7 +82,7 @@ pub fn a16_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
 }
 
 pub fn e8_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
-    let offset = bus.read_byte(addr + 1).unwrap() as i8;
+    let offset = bus.peek(addr + 1).unwrap() as i8;
     let target = if offset < 0 {
         addr.wrapping_add(2).wrapping_sub((-offset) as u16)
     } else {
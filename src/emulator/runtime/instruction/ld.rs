@@ -90,10 +90,10 @@ pub fn addr_of_hl_a(cpu: &mut cpu::CPU, bus: &mut bus::Bus, should_increase: boo
 }
 
 pub fn a_addr_of_hl(cpu: &mut cpu::CPU, bus: &mut bus::Bus, should_increase: bool) {
-    let value = cpu.get_register(bus, util::Register::HL);
+    let hl = cpu.get_register_pair(util::RegisterPair::HL);
+    let value = bus.read_byte_during_inc_dec(hl).unwrap();
     cpu.set_register(bus, util::Register::A, value);
 
-    let hl = cpu.get_register_pair(util::RegisterPair::HL);
     if should_increase {
         cpu.set_register_pair(util::RegisterPair::HL, hl.wrapping_add(1));
     } else {
@@ -149,11 +149,11 @@ pub fn hl_sp_e8(cpu: &mut cpu::CPU, bus: &mut bus::Bus) {
 
 pub fn r8_n8_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
     let reg = util::get_register_by_code((opcode >> 3) & 0b111);
-    let content = bus.read_byte(addr + 1).unwrap();
+    let content = bus.peek(addr + 1).unwrap();
 
     Some(Disasm {
         address: addr,
-        bytes: vec![opcode, bus.read_byte(addr + 1).unwrap_or(0)],
+        bytes: vec![opcode, bus.peek(addr + 1).unwrap_or(0)],
         length: 2,
         mnemonic: format!("LD {}, ${:02X}", reg, content),
         verb: "LD".into(),
@@ -166,7 +166,7 @@ pub fn r8_n8_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
 
 pub fn r16_n16_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
     let pair = util::get_register_pair_by_code((opcode >> 4) & 0b11);
-    let content = bus.read_word(addr + 1).unwrap();
+    let content = bus.peek_word(addr + 1).unwrap();
 
     Some(Disasm {
         address: addr,
@@ -258,7 +258,7 @@ pub fn a_addr_of_hl_disasm(_bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Dis
 }
 
 pub fn a16_a_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
-    let target = bus.read_word(addr).unwrap();
+    let target = bus.peek_word(addr).unwrap();
 
     Some(Disasm {
         address: addr,
@@ -271,7 +271,7 @@ pub fn a16_a_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
 }
 
 pub fn a_a16_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
-    let source = bus.read_word(addr).unwrap();
+    let source = bus.peek_word(addr).unwrap();
 
     Some(Disasm {
         address: addr,
@@ -284,7 +284,7 @@ pub fn a_a16_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
 }
 
 pub fn a16_sp_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
-    let dest = bus.read_word(addr).unwrap();
+    let dest = bus.peek_word(addr).unwrap();
 
     Some(Disasm {
         address: addr,
@@ -297,7 +297,7 @@ pub fn a16_sp_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
 }
 
 pub fn hl_sp_e8_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
-    let offset = bus.read_byte(addr + 1).unwrap();
+    let offset = bus.peek(addr + 1).unwrap();
 
     Some(Disasm {
         address: addr,
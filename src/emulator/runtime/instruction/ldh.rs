@@ -41,7 +41,7 @@ pub fn a_c(cpu: &mut cpu::CPU, bus: &mut bus::Bus) {
 }
 
 pub fn a8_a_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
-    let offset = bus.read_byte(addr + 1).unwrap();
+    let offset = bus.peek(addr + 1).unwrap();
 
     Some(Disasm {
         address: addr,
@@ -57,7 +57,7 @@ pub fn a8_a_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
 }
 
 pub fn a_a8_disasm(bus: &bus::Bus, addr: u16, opcode: u8) -> Option<Disasm> {
-    let offset = bus.read_byte(addr + 1).unwrap();
+    let offset = bus.peek(addr + 1).unwrap();
 
     Some(Disasm {
         address: addr,
@@ -19,6 +19,7 @@ pub fn ret(cpu: &mut cpu::CPU, bus: &mut bus::Bus, opcode: u8) {
     };
 
     if should_jump || opcode == 0o311 | 0o331 {
+        cpu.is_branch_taken = true;
         if opcode == 0o331 {
             bus.interrupts.ime = true;
         }
@@ -0,0 +1,66 @@
+use std::fmt;
+use std::str::FromStr;
+
+// which console is being emulated. the cgb runs everything a dmg does, but only turns
+// on its own features for cartridges that ask for them in the header. anything else it
+// runs in compatibility mode, which looks like a dmg to the game, apart from the colours
+// its boot rom picks for it. the sgb is a dmg inside a snes, which talks to the game
+// through the joypad register and draws a border around the lcd.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    #[default]
+    Dmg,
+    Cgb,
+    CgbCompat,
+    Sgb,
+}
+
+impl Model {
+    // 0x80 marks cartridges that support cgb features, 0xC0 ones that require them
+    pub fn for_cartridge(rom: &[u8]) -> Self {
+        match rom.get(0x0143) {
+            Some(0x80 | 0xC0) => Model::Cgb,
+            _ => Model::Dmg,
+        }
+    }
+
+    // what a cgb boots the cartridge into
+    pub fn for_cartridge_on_cgb(rom: &[u8]) -> Self {
+        match Self::for_cartridge(rom) {
+            Model::Cgb => Model::Cgb,
+            _ => Model::CgbCompat,
+        }
+    }
+
+    // whether the cgb features are turned on
+    pub fn is_cgb(self) -> bool {
+        self == Model::Cgb
+    }
+
+    pub fn is_cgb_hardware(self) -> bool {
+        matches!(self, Model::Cgb | Model::CgbCompat)
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Model::Dmg => "dmg",
+            Model::Cgb => "cgb",
+            Model::CgbCompat => "cgb-compat",
+            Model::Sgb => "sgb",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Model::Dmg, Model::Cgb, Model::CgbCompat, Model::Sgb]
+            .into_iter()
+            .find(|model| model.to_string() == s)
+            .ok_or_else(|| format!("unknown model {:?}", s))
+    }
+}
@@ -1,59 +1,1348 @@
-use crate::emulator::runtime::bus::Bus;
+use crate::emulator::host::triple_buffer::Writer;
+use crate::emulator::runtime::bus::device::Device;
+use crate::emulator::runtime::bus::error::BusError;
+use crate::emulator::runtime::bus::io::interrupts::{InterruptKind, Interrupts};
+use crate::emulator::runtime::model::Model;
+use crate::emulator::runtime::sgb::{self, Packet, Sgb, SgbFrameBuffer};
+use overlay::Overlays;
+use palette::{Palette, PaletteRam, Palettes};
+
+pub mod debug;
+pub mod fifo;
+pub mod oam_bug;
+pub mod overlay;
+pub mod palette;
 
 pub const WIDTH: usize = 160;
 pub const HEIGHT: usize = 144;
 pub const BPP: usize = 4;
 
-// white, light gray, dark gray, black
+pub const DOTS_PER_LINE: u16 = 456;
+pub const LINES_PER_FRAME: u8 = 154;
+const OAM_SCAN_DOTS: u16 = 80;
+const DRAWING_DOTS: u16 = 172;
+
+// white, light gray, dark gray, black. see `palette` for the other ones.
 pub const BG_PALETTE: [u32; 4] = [0xE0F8D0FF, 0x88C070FF, 0x346856FF, 0x081820FF];
 
 pub const TILE_MAP_0: u16 = 0x9800;
+pub const TILE_MAP_1: u16 = 0x9C00;
 
 pub type RGBA8888 = u32;
-pub type Tile = Box<[RGBA8888; 8 * 8]>; // fixme: support 8 * 16 tile size
+// 8x16 objects are drawn as two of these stacked on top of each other
+pub type Tile = Box<[RGBA8888; 8 * 8]>;
 pub type FrameBuffer = Box<[RGBA8888; WIDTH * HEIGHT]>;
 
+// what the lcd shows while it's off, which is lighter than any palette colour
+pub const BLANK: RGBA8888 = 0xFFFFFFFF;
+
 pub fn new_buffer() -> FrameBuffer {
     Box::new([0u32; WIDTH * HEIGHT])
 }
 
-pub fn render_frame(bus: &mut Bus) {
-    // todo: respect priorities
-    // render background
-    let _background = render_background(bus);
-    // render window
-    // render objects
-    // blit layers together
+// a frame as it's handed to the frontend. `number` is the one `take_completed_frame`
+// reports for it, frames published part way through don't have one.
+#[derive(Clone)]
+pub struct PublishedFrame {
+    pub number: Option<u64>,
+    pub pixels: FrameBuffer,
+}
+
+impl Default for PublishedFrame {
+    fn default() -> Self {
+        Self {
+            number: None,
+            pixels: new_buffer(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+// lcdc bits
+const LCD_ENABLE: u8 = 1 << 7;
+const WINDOW_TILE_MAP: u8 = 1 << 6;
+const WINDOW_ENABLE: u8 = 1 << 5;
+const TILE_DATA: u8 = 1 << 4;
+const BG_TILE_MAP: u8 = 1 << 3;
+const OBJ_SIZE: u8 = 1 << 2;
+const OBJ_ENABLE: u8 = 1 << 1;
+const BG_ENABLE: u8 = 1;
+
+// object attribute bits
+const OBJ_BEHIND_BG: u8 = 1 << 7;
+const OBJ_Y_FLIP: u8 = 1 << 6;
+const OBJ_X_FLIP: u8 = 1 << 5;
+const OBJ_PALETTE: u8 = 1 << 4;
+
+// cgb bg map attribute bits, kept in vram bank 1 at the same address as the tile index
+const BG_PRIORITY: u8 = 1 << 7;
+const BG_Y_FLIP: u8 = 1 << 6;
+const BG_X_FLIP: u8 = 1 << 5;
+
+// cgb bits shared by bg map and object attributes
+const TILE_BANK: u8 = 1 << 3;
+const CGB_PALETTE: u8 = 0b111;
+
+// the ppu can only fetch this many objects per scanline, any others are dropped
+pub const OBJECTS_PER_LINE: usize = 10;
+
+// stat interrupt select bits
+const STAT_LYC: u8 = 1 << 6;
+const STAT_OAM_SCAN: u8 = 1 << 5;
+const STAT_VBLANK: u8 = 1 << 4;
+const STAT_HBLANK: u8 = 1 << 3;
+
+// how scanlines get drawn. the scanline renderer draws a whole line at the end of a
+// fixed length mode 3, the fifo renderer pushes pixels one dot at a time, which is
+// slower but gets mid-scanline register changes and the length of mode 3 right.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Renderer {
+    #[default]
+    Scanline,
+    Fifo,
+}
+
+// layers that can be hidden for debugging. hidden layers are drawn as colour 0 but
+// otherwise behave as usual, so timing and window state don't change.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Layers {
+    pub hide_background: bool,
+    pub hide_window: bool,
+    pub hide_objects: bool,
+}
+
+// the window keeps its own state across scanlines. it only starts showing up once LY
+// has matched WY during a frame, and it has its own line counter that only advances on
+// lines it was actually drawn on, instead of being derived from LY.
+#[derive(Default, Clone, Copy)]
+pub struct WindowState {
+    is_triggered: bool,
+    line: u8,
+    // set by WX=166, which makes the window cover all of the following scanline
+    covers_next_line: bool,
+}
+
+// an entry in oam
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Object {
+    // position of the bottom right corner of an 8x16 object, so (0, 0) is off screen
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub attributes: u8,
+    // position in oam, which breaks ties between objects on the same x coordinate
+    pub index: u8,
+}
+
+// the ppu owns video memory and its registers and is clocked by the cpu through the bus,
+// one dot per t-cycle. it walks through the modes of every scanline and requests
+// the vblank and stat interrupts as it goes.
+pub struct Ppu {
+    model: Model,
+    // both cgb banks, the dmg only ever uses the first one
+    vram: Box<[u8]>,
+    oam: Box<[u8]>,
+
+    lcdc: u8,
+    // only the writable interrupt select bits, the rest is computed on read
+    stat: u8,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+    // cgb only
+    vbk: u8,
+    // bit 0 set gives objects dmg style x coordinate priority, the boot rom sets it for
+    // everything but cgb games
+    opri: u8,
+    bg_palette_ram: PaletteRam,
+    obj_palette_ram: PaletteRam,
+
+    mode: Mode,
+    // position within the current scanline
+    dot: u16,
+    // the stat interrupt is requested on the rising edge of all its sources or'ed together,
+    // so one source being active blocks the others from requesting it again
+    stat_line: bool,
+
+    renderer: Renderer,
+    layers: Layers,
+    overlays: Overlays,
+    fifo: fifo::Fifo,
+    palettes: Palettes,
+    window: WindowState,
+    // objects found by the oam scan of the current scanline
+    objects: Vec<Object>,
+    // the frame that's currently being drawn, one scanline at a time
+    frame: FrameBuffer,
+    // where completed frames are published for the frontend, if anyone's listening
+    frame_writer: Option<Writer<PublishedFrame>>,
+    frames: u64,
+    has_completed_frame: bool,
+    // the lcd doesn't show the first frame after it's turned on
+    is_blank_frame: bool,
+    // colours the frame once it's complete in sgb mode
+    sgb: Option<Box<Sgb>>,
+    // set when mode 0 starts, for hblank dma
+    has_entered_hblank: bool,
+    // LY at the time the lcd was turned off outside of vblank, for the frontend to warn about
+    lcd_disabled_at: Option<u8>,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new(Model::default())
+    }
+}
+
+impl Ppu {
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            vram: vec![0; 0x4000].into_boxed_slice(),
+            oam: vec![0; 0xA0].into_boxed_slice(),
+            // register values the dmg boot rom leaves behind
+            lcdc: 0x91,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0xFC,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            vbk: 0,
+            opri: if model.is_cgb() { 0 } else { 1 },
+            bg_palette_ram: PaletteRam::default(),
+            obj_palette_ram: PaletteRam::default(),
+            mode: Mode::OamScan,
+            dot: 0,
+            stat_line: false,
+            renderer: Renderer::default(),
+            layers: Layers::default(),
+            overlays: Overlays::default(),
+            fifo: fifo::Fifo::default(),
+            palettes: if model == Model::Sgb {
+                Palettes::uniform(sgb::SHADES)
+            } else {
+                Palettes::default()
+            },
+            window: WindowState::default(),
+            objects: Vec::with_capacity(OBJECTS_PER_LINE),
+            frame: new_buffer(),
+            frame_writer: None,
+            frames: 0,
+            has_completed_frame: false,
+            is_blank_frame: false,
+            sgb: (model == Model::Sgb).then(Box::default),
+            has_entered_hblank: false,
+            lcd_disabled_at: None,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn ly(&self) -> u8 {
+        self.ly
+    }
+
+    pub fn set_frame_writer(&mut self, writer: Writer<PublishedFrame>) {
+        self.frame_writer = Some(writer);
+    }
+
+    // takes effect with the next scanline, the rest of the frame keeps the old colours.
+    // cgb and sgb games bring their own palettes, so these only apply in dmg mode.
+    pub fn set_palettes(&mut self, palettes: Palettes) {
+        if self.sgb.is_none() {
+            self.palettes = palettes;
+        }
+    }
+
+    // where frames with the sgb border around them go, only used in sgb mode
+    pub fn set_sgb_frame_writer(&mut self, writer: Writer<SgbFrameBuffer>) {
+        if let Some(sgb) = &mut self.sgb {
+            sgb.set_writer(writer);
+        }
+    }
+
+    pub fn receive_sgb_packet(&mut self, packet: &Packet) {
+        if let Some(sgb) = &mut self.sgb {
+            sgb.receive(packet);
+        }
+    }
+
+    // takes effect with the next scanline
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+    }
+
+    pub fn set_layers(&mut self, layers: Layers) {
+        self.layers = layers;
+    }
+
+    pub fn set_overlays(&mut self, overlays: Overlays) {
+        self.overlays = overlays;
+    }
+
+    // publishes the frame as far as it's drawn, so a paused frontend sees where the ppu
+    // is. only done with the scanline overlay on, otherwise the last complete frame stays.
+    pub fn publish_partial_frame(&mut self) {
+        if self.overlays.scanline {
+            self.publish_frame(Some(self.ly));
+        }
+    }
+
+    pub fn is_lcd_enabled(&self) -> bool {
+        self.lcdc & LCD_ENABLE != 0
+    }
+
+    // see `oam_bug`, only the dmg has it
+    pub fn corrupt_oam(&mut self, kind: oam_bug::Corruption) {
+        if self.model.is_cgb_hardware() || !self.is_lcd_enabled() || self.mode != Mode::OamScan {
+            return;
+        }
+        oam_bug::corrupt(&mut self.oam, (self.dot / 4) as usize, kind);
+    }
+
+    // vram is off limits to the cpu while it's being drawn from, oam also during the scan
+    pub fn is_locked(&self, addr: u16) -> bool {
+        if !self.is_lcd_enabled() {
+            return false;
+        }
+        match addr {
+            0x8000..=0x9FFF => self.mode == Mode::Drawing,
+            0xFE00..=0xFEFF => matches!(self.mode, Mode::OamScan | Mode::Drawing),
+            _ => false,
+        }
+    }
+
+    // the most recent frame, scanlines after LY still belong to the previous one
+    pub fn frame(&self) -> &FrameBuffer {
+        &self.frame
+    }
+
+    // returns the number of the frame that was finished since the last call, if any
+    pub fn take_completed_frame(&mut self) -> Option<u64> {
+        if !self.has_completed_frame {
+            return None;
+        }
+        self.has_completed_frame = false;
+        Some(self.frames)
+    }
+
+    // whether hblank started since the last call
+    pub fn take_entered_hblank(&mut self) -> bool {
+        std::mem::take(&mut self.has_entered_hblank)
+    }
+
+    // the LY the lcd was turned off at since the last call, if that was outside of vblank
+    pub fn take_lcd_disabled_outside_vblank(&mut self) -> Option<u8> {
+        self.lcd_disabled_at.take()
+    }
+
+    fn next_mode(&self) -> Mode {
+        if self.ly as usize >= HEIGHT {
+            Mode::VBlank
+        } else if self.dot < OAM_SCAN_DOTS {
+            Mode::OamScan
+        } else if self.mode == Mode::OamScan {
+            Mode::Drawing
+        } else if self.mode == Mode::Drawing && self.is_done_drawing() {
+            Mode::HBlank
+        } else {
+            self.mode
+        }
+    }
+
+    fn is_done_drawing(&self) -> bool {
+        match self.renderer {
+            Renderer::Scanline => self.dot >= OAM_SCAN_DOTS + DRAWING_DOTS,
+            Renderer::Fifo => self.fifo.is_done(),
+        }
+    }
+
+    // runs a single dot
+    fn step(&mut self, interrupts: &mut Interrupts) {
+        self.dot += 1;
+        if self.dot == DOTS_PER_LINE {
+            self.dot = 0;
+            self.ly = (self.ly + 1) % LINES_PER_FRAME;
+        }
+
+        if self.mode == Mode::Drawing && self.renderer == Renderer::Fifo {
+            let mut fifo = std::mem::take(&mut self.fifo);
+            if let Some((x, color)) = fifo.step(self, &self.window) {
+                self.put_pixel(self.ly as usize * WIDTH + x, color);
+            }
+            self.fifo = fifo;
+        }
+
+        let mode = self.next_mode();
+        if mode != self.mode {
+            self.enter(mode, interrupts);
+        }
+
+        self.update_stat_line(interrupts);
+    }
+
+    fn enter(&mut self, mode: Mode, interrupts: &mut Interrupts) {
+        self.mode = mode;
+        self.has_entered_hblank = mode == Mode::HBlank;
+        match mode {
+            Mode::OamScan => {
+                self.objects = scan_oam(self, self.ly);
+                if self.ly == self.wy {
+                    self.window.is_triggered = true;
+                }
+            }
+            Mode::Drawing if self.renderer == Renderer::Fifo => {
+                let mut fifo = std::mem::take(&mut self.fifo);
+                fifo.start_line(self);
+                self.fifo = fifo;
+            }
+            // the fifo already drew the scanline, the window line counter is all that's left
+            Mode::HBlank if self.renderer == Renderer::Fifo => {
+                self.window.line += self.fifo.has_drawn_window() as u8;
+            }
+            // the whole scanline is drawn at once, as soon as the ppu is done with it
+            Mode::HBlank => {
+                let ly = self.ly as usize;
+                let mut window = self.window;
+                let line = render_scanline(self, self.ly, &mut window, &self.objects);
+                self.window = window;
+                for (x, color) in line.into_iter().enumerate() {
+                    self.put_pixel(ly * WIDTH + x, color);
+                }
+            }
+            Mode::VBlank => {
+                interrupts.get_mut(InterruptKind::VBlank).is_requested = true;
+                self.frames += 1;
+                self.has_completed_frame = true;
+                if std::mem::take(&mut self.is_blank_frame) {
+                    self.blank_frame();
+                }
+                if let Some(mut sgb) = self.sgb.take() {
+                    if sgb.is_waiting_for_transfer() {
+                        sgb.transfer(&displayed_tiles(self));
+                    }
+                    sgb.complete_frame(&mut self.frame);
+                    self.sgb = Some(sgb);
+                }
+                self.publish_frame(None);
+                self.window = WindowState::default();
+            }
+            _ => (),
+        }
+    }
+
+    // in sgb mode the palettes hand out shades, which the sgb colours in
+    fn put_pixel(&mut self, index: usize, color: RGBA8888) {
+        self.frame[index] = match &mut self.sgb {
+            Some(sgb) => sgb.draw(index, color as u8),
+            None => color,
+        };
+    }
+
+    fn blank_frame(&mut self) {
+        self.frame.fill(BLANK);
+        if let Some(sgb) = &mut self.sgb {
+            sgb.clear();
+        }
+    }
+
+    // turns what `bg_color` / `obj_color` return into a colour that can be shown, for
+    // drawing tiles outside of the frame. that's only different in sgb mode, where
+    // they're shades.
+    fn displayable(&self, color: RGBA8888) -> RGBA8888 {
+        match &self.sgb {
+            Some(sgb) => sgb.palette_color(color as u8),
+            None => color,
+        }
+    }
+
+    fn publish_frame(&mut self, scanline: Option<u8>) {
+        let Some(mut writer) = self.frame_writer.take() else {
+            return;
+        };
+        let back = writer.back_mut();
+        back.number = scanline.is_none().then_some(self.frames);
+        back.pixels.copy_from_slice(&self.frame[..]);
+        if self.overlays.is_enabled() {
+            overlay::draw(self, &mut back.pixels, scanline);
+        }
+        writer.publish();
+        self.frame_writer = Some(writer);
+    }
+
+    fn set_lcdc(&mut self, content: u8) {
+        let was_enabled = self.is_lcd_enabled();
+        self.lcdc = content;
+
+        match (was_enabled, self.is_lcd_enabled()) {
+            // the ppu stops where it is, LY reads 0 and STAT mode 0 until it's turned back on.
+            // the lcd goes blank instead of holding on to the last frame, which counts as a
+            // frame of its own so the frontend and recordings get to see it.
+            (true, false) => {
+                self.ly = 0;
+                self.dot = 0;
+                self.mode = Mode::HBlank;
+                self.window = WindowState::default();
+                self.blank_frame();
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.complete_frame(&mut self.frame);
+                }
+                self.frames += 1;
+                self.has_completed_frame = true;
+                self.publish_frame(None);
+            }
+            // starts over from the top of the frame, which doesn't make it to the lcd
+            (false, true) => {
+                self.ly = 0;
+                self.dot = 0;
+                self.mode = Mode::OamScan;
+                self.objects = scan_oam(self, 0);
+                self.window = WindowState::default();
+                if self.wy == 0 {
+                    self.window.is_triggered = true;
+                }
+                self.is_blank_frame = true;
+            }
+            _ => (),
+        }
+    }
+
+    fn is_cgb(&self) -> bool {
+        self.model.is_cgb()
+    }
+
+    fn bg_color(&self, attributes: u8, color_index: u8) -> RGBA8888 {
+        if self.is_cgb() {
+            return self
+                .bg_palette_ram
+                .color(attributes & CGB_PALETTE, color_index);
+        }
+        shade(&self.palettes.bg, self.bgp, color_index)
+    }
+
+    fn obj_color(&self, attributes: u8, color_index: u8) -> RGBA8888 {
+        if self.is_cgb() {
+            self.obj_palette_ram
+                .color(attributes & CGB_PALETTE, color_index)
+        } else if attributes & OBJ_PALETTE != 0 {
+            shade(&self.palettes.obj1, self.obp1, color_index)
+        } else {
+            shade(&self.palettes.obj0, self.obp0, color_index)
+        }
+    }
+
+    fn object_height(&self) -> u8 {
+        if self.lcdc & OBJ_SIZE != 0 { 16 } else { 8 }
+    }
+
+    fn has_x_priority(&self) -> bool {
+        self.opri & 1 != 0
+    }
+
+    // picks between the background and object pixel of a column and returns its colour
+    fn mix(&self, (bg_index, bg_attributes): (u8, u8), object: Option<(u8, u8)>) -> RGBA8888 {
+        let is_object_visible = match object {
+            None => false,
+            // on cgb, clearing lcdc bit 0 puts objects on top of everything
+            Some(_) if self.is_cgb() && self.lcdc & BG_ENABLE == 0 => true,
+            // otherwise objects behind the background, or under a bg tile with the
+            // priority bit set, only show through its colour 0
+            Some((_, attributes)) => {
+                bg_index == 0
+                    || (attributes & OBJ_BEHIND_BG == 0 && bg_attributes & BG_PRIORITY == 0)
+            }
+        };
+
+        match object {
+            Some((color_index, attributes)) if is_object_visible => {
+                self.obj_color(attributes, color_index)
+            }
+            _ => self.bg_color(bg_attributes, bg_index),
+        }
+    }
+
+    // the bank selected by VBK, which is what the cpu sees at 0x8000-0x9FFF
+    fn vram_bank(&self) -> u8 {
+        if self.is_cgb() { self.vbk & 1 } else { 0 }
+    }
+
+    // the vram bank tiles are read from, for bg map entries and objects alike
+    fn tile_bank(&self, attributes: u8) -> u8 {
+        (self.is_cgb() && attributes & TILE_BANK != 0) as u8
+    }
+
+    fn vram_byte(&self, addr: u16) -> u8 {
+        self.vram_byte_in(0, addr)
+    }
+
+    fn vram_byte_in(&self, bank: u8, addr: u16) -> u8 {
+        self.vram[bank as usize * 0x2000 + (addr - 0x8000) as usize]
+    }
+
+    // the cgb attributes of the bg map entry at `map_address`, always 0 on dmg
+    fn map_attributes(&self, map_address: u16) -> u8 {
+        if self.is_cgb() {
+            self.vram_byte_in(1, map_address)
+        } else {
+            0
+        }
+    }
+
+    // address of a bg / window tile, depending on the addressing mode selected in lcdc.
+    // 0x8000 addressing uses the index as is, 0x8800 addressing treats it as signed
+    // offset from 0x9000.
+    fn tile_address(&self, index: u8) -> u16 {
+        if self.lcdc & TILE_DATA != 0 {
+            0x8000 + index as u16 * 16
+        } else {
+            0x9000u16.wrapping_add_signed(index as i8 as i16 * 16)
+        }
+    }
+
+    // decodes one row of pixels of the tile at `tile_address` into colour indices
+    fn tile_row_in(&self, bank: u8, tile_address: u16, row: u16) -> [u8; 8] {
+        let first = self.vram_byte_in(bank, tile_address + row * 2);
+        let second = self.vram_byte_in(bank, tile_address + row * 2 + 1);
+
+        let mut pixels = [0u8; 8];
+        for (column_index, pixel) in pixels.iter_mut().enumerate() {
+            let bit_low = (first >> (7 - column_index)) & 0x1;
+            let bit_high = (second >> (7 - column_index)) & 0x1;
+            *pixel = (bit_high << 1) | bit_low;
+        }
+        pixels
+    }
+
+    // one row of the bg / window tile referenced by the map entry at `map_address`, with
+    // its cgb attributes already applied, along with those attributes
+    fn map_tile_row(&self, map_address: u16, row: u8) -> ([u8; 8], u8) {
+        let attributes = self.map_attributes(map_address);
+        let row = if attributes & BG_Y_FLIP != 0 {
+            7 - row
+        } else {
+            row
+        };
+        let tile_address = self.tile_address(self.vram_byte(map_address));
+        let mut pixels = self.tile_row_in(self.tile_bank(attributes), tile_address, row as u16);
+        if attributes & BG_X_FLIP != 0 {
+            pixels.reverse();
+        }
+        (pixels, attributes)
+    }
+
+    fn update_stat_line(&mut self, interrupts: &mut Interrupts) {
+        let is_selected = |bit: u8| self.stat & bit != 0;
+
+        // the oam scan source also fires when entering vblank, even though mode 2 never starts
+        let line = (is_selected(STAT_LYC) && self.ly == self.lyc)
+            || (is_selected(STAT_HBLANK) && self.mode == Mode::HBlank)
+            || (is_selected(STAT_VBLANK) && self.mode == Mode::VBlank)
+            || (is_selected(STAT_OAM_SCAN)
+                && (self.mode == Mode::OamScan || (self.ly as usize == HEIGHT && self.dot == 0)));
+
+        if line && !self.stat_line {
+            interrupts.get_mut(InterruptKind::LCDStat).is_requested = true;
+        }
+        self.stat_line = line;
+    }
+
+    fn read_stat(&self) -> u8 {
+        let coincidence = if self.ly == self.lyc { 0b100 } else { 0 };
+        0b1000_0000 | self.stat | coincidence | self.mode as u8
+    }
+}
+
+impl Device for Ppu {
+    fn read(&self, addr: u16) -> Result<u8, BusError> {
+        match addr {
+            0x8000..=0x9FFF => Ok(self.vram_byte_in(self.vram_bank(), addr)),
+            0xFE00..=0xFE9F => Ok(self.oam[(addr - 0xFE00) as usize]),
+            // the unusable area after oam, which reads 0 on dmg
+            0xFEA0..=0xFEFF => Ok(0x00),
+            0xFF40 => Ok(self.lcdc),
+            0xFF41 => Ok(self.read_stat()),
+            0xFF42 => Ok(self.scy),
+            0xFF43 => Ok(self.scx),
+            0xFF44 => Ok(self.ly),
+            0xFF45 => Ok(self.lyc),
+            0xFF47 => Ok(self.bgp),
+            0xFF48 => Ok(self.obp0),
+            0xFF49 => Ok(self.obp1),
+            0xFF4A => Ok(self.wy),
+            0xFF4B => Ok(self.wx),
+            // the cgb registers read as open bus on dmg
+            0xFF4F | 0xFF68..=0xFF6C if !self.is_cgb() => Ok(0xFF),
+            0xFF4F => Ok(0b1111_1110 | self.vbk),
+            0xFF68 => Ok(self.bg_palette_ram.read_spec()),
+            0xFF69 => Ok(self.bg_palette_ram.read_data()),
+            0xFF6A => Ok(self.obj_palette_ram.read_spec()),
+            0xFF6B => Ok(self.obj_palette_ram.read_data()),
+            0xFF6C => Ok(0b1111_1110 | self.opri),
+            _ => Err(BusError::Unimplemented(addr)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        addr: u16,
+        content: u8,
+        interrupts: &mut Interrupts,
+    ) -> Result<(), BusError> {
+        match addr {
+            // real hardware can only turn the lcd off during vblank, doing it any other time
+            // is said to damage the screen. it works just fine here, but it's worth knowing.
+            0xFF40
+                if self.is_lcd_enabled()
+                    && content & LCD_ENABLE == 0
+                    && self.mode != Mode::VBlank =>
+            {
+                self.lcd_disabled_at = Some(self.ly);
+                self.set_lcdc(content);
+            }
+            0xFF40 => self.set_lcdc(content),
+            // writes through the data registers advance the index, pokes don't
+            0xFF69 if self.is_cgb() => self.bg_palette_ram.write_data(content),
+            0xFF6B if self.is_cgb() => self.obj_palette_ram.write_data(content),
+            _ => self.poke(addr, content)?,
+        }
+        if matches!(addr, 0xFF41 | 0xFF45) {
+            self.update_stat_line(interrupts);
+        }
+        Ok(())
+    }
+
+    fn poke(&mut self, addr: u16, content: u8) -> Result<(), BusError> {
+        match addr {
+            0x8000..=0x9FFF => {
+                self.vram[self.vram_bank() as usize * 0x2000 + (addr - 0x8000) as usize] = content
+            }
+            0xFE00..=0xFE9F => self.oam[(addr - 0xFE00) as usize] = content,
+            0xFEA0..=0xFEFF => (),
+            // straight into the register, a debugger turning the lcd off doesn't blank it
+            0xFF40 => self.lcdc = content,
+            0xFF41 => self.stat = content & 0b0111_1000,
+            0xFF42 => self.scy = content,
+            0xFF43 => self.scx = content,
+            // LY is read only
+            0xFF44 => (),
+            0xFF45 => self.lyc = content,
+            0xFF47 => self.bgp = content,
+            0xFF48 => self.obp0 = content,
+            0xFF49 => self.obp1 = content,
+            0xFF4A => self.wy = content,
+            0xFF4B => self.wx = content,
+            0xFF4F | 0xFF68..=0xFF6C if !self.is_cgb() => (),
+            0xFF4F => self.vbk = content & 1,
+            0xFF68 => self.bg_palette_ram.write_spec(content),
+            0xFF69 => self.bg_palette_ram.poke_data(content),
+            0xFF6A => self.obj_palette_ram.write_spec(content),
+            0xFF6B => self.obj_palette_ram.poke_data(content),
+            0xFF6C => self.opri = content & 1,
+            _ => return Err(BusError::Unimplemented(addr)),
+        }
+        Ok(())
+    }
+
+    fn bank_of(&self, addr: u16) -> u16 {
+        match addr {
+            0x8000..=0x9FFF => self.vram_bank() as u16,
+            _ => 0,
+        }
+    }
+
+    // both vram banks can be inspected, no matter which one VBK selects
+    fn peek_bank(&self, bank: u16, addr: u16) -> Result<u8, BusError> {
+        match addr {
+            0x8000..=0x9FFF if bank < 2 => {
+                Ok(self.vram[bank as usize * 0x2000 + (addr - 0x8000) as usize])
+            }
+            _ if bank == 0 => self.peek(addr),
+            _ => Err(BusError::NoSuchBank(bank, addr)),
+        }
+    }
+
+    fn poke_bank(&mut self, bank: u16, addr: u16, content: u8) -> Result<(), BusError> {
+        match addr {
+            0x8000..=0x9FFF if bank < 2 => {
+                self.vram[bank as usize * 0x2000 + (addr - 0x8000) as usize] = content;
+                Ok(())
+            }
+            _ if bank == 0 => self.poke(addr, content),
+            _ => Err(BusError::NoSuchBank(bank, addr)),
+        }
+    }
+
+    fn tick(&mut self, cycles: u8, interrupts: &mut Interrupts) {
+        if !self.is_lcd_enabled() {
+            return;
+        }
+        for _ in 0..cycles {
+            self.step(interrupts);
+        }
+    }
+
+    fn runs_at_double_speed(&self) -> bool {
+        false
+    }
+}
+
+// renders a whole frame from the current state of vram and the registers, as opposed to
+// the scanline by scanline rendering the ppu does while it's running
+pub fn render_frame(ppu: &Ppu) -> FrameBuffer {
+    let mut buffer = new_buffer();
+
+    let mut window = WindowState::default();
+    for ly in 0..HEIGHT {
+        let objects = scan_oam(ppu, ly as u8);
+        let line = render_scanline(ppu, ly as u8, &mut window, &objects);
+        buffer[ly * WIDTH..(ly + 1) * WIDTH].copy_from_slice(&line);
+    }
+
+    buffer
 }
 
-pub fn render_background(bus: &mut Bus) -> FrameBuffer {
-    let buffer = new_buffer();
+pub fn render_scanline(
+    ppu: &Ppu,
+    ly: u8,
+    window: &mut WindowState,
+    objects: &[Object],
+) -> [RGBA8888; WIDTH] {
+    let mut background = background_line(ppu, ly);
+    window_line(ppu, ly, window, &mut background);
+    let objects = object_line(ppu, ly, objects);
 
-    for y in 0..HEIGHT as u16 - 1 {
-        for x in 0..WIDTH as u16 - 1 {
-            let tile_index = bus.read_byte(TILE_MAP_0 + x + y * 32).unwrap();
-            let tile_address = (tile_index as u16) * 16;
-            let _tile = read_tile(bus, tile_address);
+    let mut line = [0; WIDTH];
+    for (x, pixel) in line.iter_mut().enumerate() {
+        *pixel = ppu.mix(background[x], objects[x]);
+    }
+    line
+}
+
+// selects the objects overlapping scanline `ly`, in oam order. objects that are
+// off screen horizontally still count towards the limit.
+pub fn scan_oam(ppu: &Ppu, ly: u8) -> Vec<Object> {
+    let height = ppu.object_height();
+    let line = ly as u16 + 16;
+
+    ppu.oam
+        .chunks_exact(4)
+        .enumerate()
+        .map(|(index, entry)| Object {
+            y: entry[0],
+            x: entry[1],
+            tile: entry[2],
+            attributes: entry[3],
+            index: index as u8,
+        })
+        .filter(|object| {
+            let top = object.y as u16;
+            line >= top && line < top + height as u16
+        })
+        .take(OBJECTS_PER_LINE)
+        .collect()
+}
+
+// colour index and attributes of the object pixel that wins each column of the scanline
+fn object_line(ppu: &Ppu, ly: u8, objects: &[Object]) -> [Option<(u8, u8)>; WIDTH] {
+    let mut line = [None; WIDTH];
+
+    if ppu.lcdc & OBJ_ENABLE == 0 || ppu.layers.hide_objects {
+        return line;
+    }
+
+    // on dmg the object with the smaller x coordinate wins, then the one that comes
+    // first in oam. cgb games only go by oam order (unless they change OPRI), which is
+    // the order the scan found them in. drawing them in reverse lets the winner's opaque
+    // pixels end up on top, while its transparent ones still let lower priority objects
+    // through.
+    let mut objects = objects.to_vec();
+    if ppu.has_x_priority() {
+        objects.sort_by_key(|object| (object.x, object.index));
+    }
+
+    let height = ppu.object_height();
+    for object in objects.iter().rev() {
+        let mut row = (ly as u16 + 16 - object.y as u16) as u8;
+        if object.attributes & OBJ_Y_FLIP != 0 {
+            row = height - 1 - row;
+        }
+
+        // in 8x16 mode the lowest bit of the index is ignored, the bottom half is the next tile
+        let tile = if height == 16 {
+            object.tile & 0xFE
+        } else {
+            object.tile
+        };
+        let mut pixels = ppu.tile_row_in(
+            ppu.tile_bank(object.attributes),
+            0x8000 + tile as u16 * 16,
+            row as u16,
+        );
+        if object.attributes & OBJ_X_FLIP != 0 {
+            pixels.reverse();
+        }
+
+        for (column, color_index) in pixels.into_iter().enumerate() {
+            let x = object.x as usize + column;
+            // colour 0 is transparent for objects
+            if color_index == 0 || !(8..WIDTH + 8).contains(&x) {
+                continue;
+            }
+            line[x - 8] = Some((color_index, object.attributes));
+        }
+    }
+
+    line
+}
+
+// the tile data of what's on screen, going through the background map row by row. this
+// is how sgb transfers get their data: games put the bytes they want to send into tiles
+// and show those.
+fn displayed_tiles(ppu: &Ppu) -> Vec<u8> {
+    let tile_map = if ppu.lcdc & BG_TILE_MAP != 0 {
+        TILE_MAP_1
+    } else {
+        TILE_MAP_0
+    };
+
+    let tiles_per_row = (WIDTH / 8) as u16;
+    (0..sgb::TRANSFER_SIZE as u16 / 16)
+        .flat_map(|index| {
+            let map_address = tile_map + (index / tiles_per_row) * 32 + index % tiles_per_row;
+            let tile_address = ppu.tile_address(ppu.vram_byte(map_address));
+            (0..16).map(move |offset| ppu.vram_byte(tile_address + offset))
+        })
+        .collect()
+}
+
+pub fn render_background(ppu: &Ppu) -> FrameBuffer {
+    let mut buffer = new_buffer();
+
+    for ly in 0..HEIGHT {
+        let line = background_line(ppu, ly as u8);
+        for (x, (color_index, attributes)) in line.into_iter().enumerate() {
+            buffer[ly * WIDTH + x] = ppu.displayable(ppu.bg_color(attributes, color_index));
         }
     }
 
     buffer
 }
 
-fn read_tile(bus: &mut Bus, base_index: u16) -> Tile {
+// colour indices of the background on scanline `ly`, before they go through the
+// palettes, along with the cgb attributes of the tiles they belong to
+fn background_line(ppu: &Ppu, ly: u8) -> [(u8, u8); WIDTH] {
+    let mut line = [(0, 0); WIDTH];
+
+    // on dmg, clearing lcdc bit 0 blanks the background to colour 0
+    if (!ppu.is_cgb() && ppu.lcdc & BG_ENABLE == 0) || ppu.layers.hide_background {
+        return line;
+    }
+
+    let tile_map = if ppu.lcdc & BG_TILE_MAP != 0 {
+        TILE_MAP_1
+    } else {
+        TILE_MAP_0
+    };
+
+    // the background map is 256x256 pixels and wraps around in both directions
+    let y = ly.wrapping_add(ppu.scy);
+    let tile_row = (y / 8) as u16;
+
+    let mut x = ppu.scx;
+    let mut screen_x = 0;
+    while screen_x < WIDTH {
+        let tile_column = (x / 8) as u16;
+        let (row, attributes) = ppu.map_tile_row(tile_map + tile_row * 32 + tile_column, y % 8);
+
+        // the first tile may be cut off by the fine scroll
+        for &color_index in &row[(x % 8) as usize..] {
+            if screen_x == WIDTH {
+                break;
+            }
+            line[screen_x] = (color_index, attributes);
+            screen_x += 1;
+            x = x.wrapping_add(1);
+        }
+    }
+
+    line
+}
+
+// draws the window over the background pixels of scanline `ly`
+fn window_line(ppu: &Ppu, ly: u8, window: &mut WindowState, line: &mut [(u8, u8); WIDTH]) {
+    if ly == ppu.wy {
+        window.is_triggered = true;
+    }
+
+    // on dmg, lcdc bit 0 turns off the window along with the background
+    let is_enabled = ppu.lcdc & WINDOW_ENABLE != 0 && (ppu.is_cgb() || ppu.lcdc & BG_ENABLE != 0);
+    let covers_line = std::mem::take(&mut window.covers_next_line);
+    if !is_enabled || !window.is_triggered {
+        return;
+    }
+
+    // WX is offset by 7, values below that cut off the left side of the window instead
+    // of moving it further left. 166 is the odd one out: nothing is drawn on this line,
+    // but the window then covers the whole next one.
+    let (start, skip) = match ppu.wx {
+        _ if covers_line => (0, 0),
+        166 => {
+            window.covers_next_line = true;
+            return;
+        }
+        wx if wx < 7 => (0, 7 - wx),
+        wx if wx as usize - 7 < WIDTH => (wx as usize - 7, 0),
+        _ => return,
+    };
+
+    let tile_map = if ppu.lcdc & WINDOW_TILE_MAP != 0 {
+        TILE_MAP_1
+    } else {
+        TILE_MAP_0
+    };
+    let tile_row = (window.line / 8) as u16;
+
+    for (screen_x, pixel) in line.iter_mut().enumerate().skip(start) {
+        if ppu.layers.hide_window {
+            *pixel = (0, 0);
+            continue;
+        }
+        let x = (screen_x - start) as u16 + skip as u16;
+        let (row, attributes) = ppu.map_tile_row(tile_map + tile_row * 32 + x / 8, window.line % 8);
+        *pixel = (row[(x % 8) as usize], attributes);
+    }
+
+    window.line += 1;
+}
+
+// maps a colour index through a dmg palette register to one of the colours it's displayed as
+fn shade(colors: &Palette, palette: u8, color_index: u8) -> RGBA8888 {
+    colors[((palette >> (color_index * 2)) & 0b11) as usize]
+}
+
+pub fn read_tile(ppu: &Ppu, base_index: u16) -> Tile {
+    read_tile_in(ppu, 0, base_index)
+}
+
+// same as `read_tile`, but from either of the cgb vram banks
+pub fn read_tile_in(ppu: &Ppu, bank: u8, base_index: u16) -> Tile {
     let mut tile = [0u32; 8 * 8]; // fixme: ideally use RGBA8888 here instead of u32
 
     for row_index in 0..8 {
-        let first = bus.read_byte(base_index + row_index * 2).unwrap();
-        let second = bus.read_byte(base_index + row_index * 2 + 1).unwrap();
+        let row = ppu.tile_row_in(bank, base_index, row_index);
 
-        for column_index in 0..8 {
-            let bit_low = (first >> 7 - column_index) & 0x1;
-            let bit_high = (second >> 7 - column_index) & 0x1;
-            let color_index = (bit_high << 1) | bit_low;
-            tile[(row_index * 8 + column_index) as usize] = BG_PALETTE[color_index as usize];
+        for (column_index, color_index) in row.into_iter().enumerate() {
+            tile[row_index as usize * 8 + column_index] =
+                ppu.displayable(ppu.bg_color(0, color_index));
         }
     }
 
     Box::new(tile)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::host::triple_buffer::triple_buffer;
+    use crate::emulator::runtime::ppu::palette::rgba_from_rgb555;
+
+    // colour 0 of the palettes the sgb starts out with
+    const SGB_COLOR_0: u16 = 0x67BF;
+
+    fn run_lines(ppu: &mut Ppu, lines: usize, interrupts: &mut Interrupts) {
+        for _ in 0..lines * DOTS_PER_LINE as usize / 4 {
+            ppu.tick(4, interrupts);
+        }
+    }
+
+    // every colour is the ascii code of what it stands for: '.' and '1'-'3' for the
+    // background, 'a'-'c' for obj0 and 'A'-'C' for obj1
+    const TEXT_PALETTES: Palettes = Palettes {
+        bg: [b'.' as u32, b'1' as u32, b'2' as u32, b'3' as u32],
+        obj0: [0, b'a' as u32, b'b' as u32, b'c' as u32],
+        obj1: [0, b'A' as u32, b'B' as u32, b'C' as u32],
+    };
+
+    // tiles for the object tests, from tile 1 on. '.' is colour 0.
+    const TILES: [[&str; 8]; 4] = [
+        [
+            "1.......", "1.......", "1.......", "1.......", "1.......", "1.......", "1.......",
+            "22222223",
+        ],
+        ["11111111"; 8],
+        ["22222222"; 8],
+        ["1111...."; 8],
+    ];
+
+    // a dmg with palettes that map every colour index to itself, `TILES` in vram and a
+    // blank background
+    fn ppu_with_tiles(renderer: Renderer) -> Ppu {
+        let mut ppu = Ppu::new(Model::Dmg);
+        ppu.set_renderer(renderer);
+        ppu.set_palettes(TEXT_PALETTES);
+        for (tile, rows) in TILES.iter().enumerate() {
+            for (row, pixels) in rows.iter().enumerate() {
+                let (mut low, mut high) = (0, 0);
+                for (x, pixel) in pixels.bytes().enumerate() {
+                    let index = pixel.saturating_sub(b'0');
+                    low |= (index & 1) << (7 - x);
+                    high |= (index >> 1) << (7 - x);
+                }
+                let addr = 0x8010 + tile as u16 * 16 + row as u16 * 2;
+                ppu.poke(addr, low).unwrap();
+                ppu.poke(addr + 1, high).unwrap();
+            }
+        }
+        for addr in [0xFF47, 0xFF48, 0xFF49] {
+            ppu.poke(addr, 0xE4).unwrap();
+        }
+        ppu
+    }
+
+    // puts object `index` at the given position on screen
+    fn place(ppu: &mut Ppu, index: u16, x: u8, y: u8, tile: u8, attributes: u8) {
+        let addr = 0xFE00 + index * 4;
+        for (offset, content) in [y + 16, x + 8, tile, attributes].into_iter().enumerate() {
+            ppu.poke(addr + offset as u16, content).unwrap();
+        }
+    }
+
+    // runs the ppu with the given LCDC and returns the top left `width` x
+    // `height` pixels as text, see `TEXT_PALETTES`
+    fn snapshot(ppu: &mut Ppu, lcdc: u8, width: usize, height: usize) -> Vec<String> {
+        ppu.poke(0xFF40, lcdc).unwrap();
+        // the first line was already scanned before the test set up oam, so it's the
+        // second frame that counts
+        run_lines(
+            ppu,
+            2 * LINES_PER_FRAME as usize,
+            &mut Interrupts::default(),
+        );
+        ppu.frame()
+            .chunks(WIDTH)
+            .take(height)
+            .map(|row| {
+                row[..width]
+                    .iter()
+                    .map(|&pixel| pixel as u8 as char)
+                    .collect()
+            })
+            .collect()
+    }
+
+    const RENDERERS: [Renderer; 2] = [Renderer::Scanline, Renderer::Fifo];
+
+    #[test]
+    fn sgb_frames_hold_colours_not_shades() {
+        let mut ppu = Ppu::new(Model::Sgb);
+        let mut interrupts = Interrupts::default();
+        run_lines(&mut ppu, 2, &mut interrupts);
+
+        let color0 = rgba_from_rgb555(SGB_COLOR_0);
+        assert!(
+            ppu.frame()[..2 * WIDTH]
+                .iter()
+                .all(|&pixel| pixel == color0)
+        );
+    }
+
+    #[test]
+    fn turning_the_lcd_off_blanks_it_in_sgb_colours() {
+        let mut ppu = Ppu::new(Model::Sgb);
+        let mut interrupts = Interrupts::default();
+        run_lines(&mut ppu, 2, &mut interrupts);
+        ppu.write(0xFF40, 0x11, &mut interrupts).unwrap();
+
+        let color0 = rgba_from_rgb555(SGB_COLOR_0);
+        assert!(ppu.frame().iter().all(|&pixel| pixel == color0));
+    }
+
+    #[test]
+    fn turning_the_lcd_off_blanks_it_in_white() {
+        let mut ppu = Ppu::new(Model::Dmg);
+        let mut interrupts = Interrupts::default();
+        run_lines(&mut ppu, 2, &mut interrupts);
+        ppu.write(0xFF40, 0x11, &mut interrupts).unwrap();
+
+        assert!(ppu.frame().iter().all(|&pixel| pixel == BLANK));
+    }
+
+    #[test]
+    fn the_blank_frame_counts_as_completed() {
+        let mut ppu = Ppu::new(Model::Dmg);
+        let mut interrupts = Interrupts::default();
+        run_lines(&mut ppu, LINES_PER_FRAME as usize + 2, &mut interrupts);
+        assert_eq!(ppu.take_completed_frame(), Some(1));
+
+        ppu.write(0xFF40, 0x11, &mut interrupts).unwrap();
+        assert_eq!(ppu.take_completed_frame(), Some(2));
+        assert_eq!(ppu.take_completed_frame(), None);
+    }
+
+    #[test]
+    fn published_frames_carry_their_number() {
+        let mut ppu = Ppu::new(Model::Dmg);
+        let mut interrupts = Interrupts::default();
+        let (writer, mut reader) = triple_buffer(PublishedFrame::default());
+        ppu.set_frame_writer(writer);
+        ppu.set_overlays(Overlays {
+            scanline: true,
+            ..Overlays::default()
+        });
+
+        run_lines(&mut ppu, HEIGHT + 1, &mut interrupts);
+        assert_eq!(reader.latest().number, Some(1));
+
+        // half drawn ones have none
+        run_lines(&mut ppu, LINES_PER_FRAME as usize, &mut interrupts);
+        ppu.publish_partial_frame();
+        assert_eq!(reader.latest().number, None);
+    }
+
+    #[test]
+    fn poking_lcdc_only_changes_the_register() {
+        let mut ppu = Ppu::new(Model::Dmg);
+        let mut interrupts = Interrupts::default();
+        run_lines(&mut ppu, 2, &mut interrupts);
+        let frame = ppu.frame().to_vec();
+
+        ppu.poke(0xFF40, 0x11).unwrap();
+        assert_eq!(ppu.peek(0xFF40).unwrap(), 0x11);
+        assert_eq!(ppu.peek(0xFF44).unwrap(), 2);
+        assert_eq!(ppu.frame()[..], frame[..]);
+        assert_eq!(ppu.take_completed_frame(), None);
+        assert_eq!(ppu.take_lcd_disabled_outside_vblank(), None);
+    }
+
+    #[test]
+    fn turning_the_lcd_off_outside_of_vblank_is_reported() {
+        let mut ppu = Ppu::new(Model::Dmg);
+        let mut interrupts = Interrupts::default();
+        run_lines(&mut ppu, 2, &mut interrupts);
+        ppu.write(0xFF40, 0x11, &mut interrupts).unwrap();
+        assert_eq!(ppu.take_lcd_disabled_outside_vblank(), Some(2));
+        assert_eq!(ppu.take_lcd_disabled_outside_vblank(), None);
+
+        // during vblank it's fine
+        ppu.write(0xFF40, 0x91, &mut interrupts).unwrap();
+        run_lines(&mut ppu, HEIGHT + 1, &mut interrupts);
+        ppu.write(0xFF40, 0x11, &mut interrupts).unwrap();
+        assert_eq!(ppu.take_lcd_disabled_outside_vblank(), None);
+    }
+
+    #[test]
+    fn objects_flip_and_pick_their_palette() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            place(&mut ppu, 0, 0, 0, 1, 0);
+            place(&mut ppu, 1, 8, 0, 1, OBJ_X_FLIP);
+            place(&mut ppu, 2, 16, 0, 1, OBJ_Y_FLIP | OBJ_PALETTE);
+            place(&mut ppu, 3, 24, 0, 1, OBJ_X_FLIP | OBJ_Y_FLIP | OBJ_PALETTE);
+
+            let top = ["a..............aBBBBBBBCCBBBBBBB"];
+            let middle = ["a..............aA..............A"; 6];
+            let bottom = ["bbbbbbbccbbbbbbbA..............A"];
+            let expected = [&top[..], &middle[..], &bottom[..]].concat();
+            assert_eq!(snapshot(&mut ppu, 0x93, 32, 8), expected, "{:?}", renderer);
+        }
+    }
+
+    #[test]
+    fn tall_objects_use_a_pair_of_tiles() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            // the lowest bit of the tile index is ignored
+            place(&mut ppu, 0, 0, 0, 3, 0);
+            place(&mut ppu, 1, 8, 0, 2, OBJ_Y_FLIP);
+
+            let expected = [["aaaaaaaabbbbbbbb"; 8], ["bbbbbbbbaaaaaaaa"; 8]].concat();
+            let snapshot = snapshot(&mut ppu, 0x93 | OBJ_SIZE, 16, 16);
+            assert_eq!(snapshot, expected, "{:?}", renderer);
+        }
+    }
+
+    #[test]
+    fn dmg_objects_further_left_win_then_lower_oam_indices() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            place(&mut ppu, 0, 4, 0, 2, OBJ_PALETTE);
+            place(&mut ppu, 1, 0, 0, 3, 0);
+            place(&mut ppu, 2, 16, 0, 2, 0);
+            place(&mut ppu, 3, 16, 0, 3, OBJ_PALETTE);
+            // transparent pixels show whatever object is below
+            place(&mut ppu, 4, 32, 0, 1, 0);
+            place(&mut ppu, 5, 32, 0, 2, OBJ_PALETTE);
+
+            let snapshot = snapshot(&mut ppu, 0x93, 40, 8);
+            assert_eq!(
+                snapshot[0], "bbbbbbbbAAAA....aaaaaaaa........aAAAAAAA",
+                "{:?}",
+                renderer
+            );
+            assert_eq!(
+                snapshot[7], "bbbbbbbbAAAA....aaaaaaaa........bbbbbbbc",
+                "{:?}",
+                renderer
+            );
+        }
+    }
+
+    #[test]
+    fn objects_behind_the_background_only_show_over_colour_0() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            ppu.poke(0x9800, 4).unwrap();
+            place(&mut ppu, 0, 0, 0, 2, OBJ_BEHIND_BG);
+            place(&mut ppu, 1, 8, 0, 2, OBJ_BEHIND_BG);
+
+            let snapshot = snapshot(&mut ppu, 0x93, 16, 8);
+            assert_eq!(snapshot, ["1111aaaaaaaaaaaa"; 8], "{:?}", renderer);
+        }
+    }
+
+    #[test]
+    fn only_ten_objects_make_it_onto_a_line() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            for index in 0..11 {
+                place(&mut ppu, index, index as u8 * 8, 0, 2, 0);
+            }
+
+            let snapshot = snapshot(&mut ppu, 0x93, 88, 8);
+            let expected = "a".repeat(80) + "........";
+            assert!(
+                snapshot.iter().all(|row| *row == expected),
+                "{:?}",
+                renderer
+            );
+        }
+    }
+
+    #[test]
+    fn sgb_debug_views_are_coloured_too() {
+        let ppu = Ppu::new(Model::Sgb);
+        let image = debug::tile_sheet(&ppu, 0);
+        let color0 = rgba_from_rgb555(SGB_COLOR_0);
+        assert!(image.pixels.iter().all(|&pixel| pixel == color0));
+    }
+}
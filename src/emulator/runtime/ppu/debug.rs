@@ -0,0 +1,256 @@
+use super::{
+    BG_TILE_MAP, CGB_PALETTE, HEIGHT, OBJ_BEHIND_BG, OBJ_PALETTE, OBJ_X_FLIP, OBJ_Y_FLIP, Object,
+    Ppu, RGBA8888, TILE_MAP_0, TILE_MAP_1, WIDTH, WINDOW_ENABLE, WINDOW_TILE_MAP, read_tile_in,
+};
+
+// images of what's in vram and oam right now, for debugger views. nothing in here has any
+// effect on the ppu, so they can be rendered at any point, even mid frame.
+
+// tile data at 0x8000-0x97FF is 384 tiles, laid out 16 per row like most debuggers do
+pub const TILES: usize = 384;
+const TILES_PER_ROW: usize = 16;
+
+// the bg maps are 32x32 tiles
+pub const TILE_MAP_SIZE: usize = 256;
+
+pub const OBJECTS: usize = 40;
+
+// outlines drawn onto the tile maps
+pub const VIEWPORT_COLOR: RGBA8888 = 0xFF0000FF;
+pub const WINDOW_COLOR: RGBA8888 = 0x0000FFFF;
+
+// colour 0 of objects is see-through, so their thumbnails leave it transparent
+const TRANSPARENT: RGBA8888 = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<RGBA8888>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: RGBA8888) {
+        self.pixels[y * self.width + x] = color;
+    }
+}
+
+// an oam entry along with what it looks like and its attributes picked apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectEntry {
+    pub object: Object,
+    // 8x8 or 8x16 depending on lcdc, flipped like it is on screen
+    pub thumbnail: Image,
+    pub is_behind_bg: bool,
+    pub is_x_flipped: bool,
+    pub is_y_flipped: bool,
+    // OBP0 / OBP1 on dmg, one of the eight object palettes on cgb
+    pub palette: u8,
+    pub bank: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    Tiles { bank: u8 },
+    // either `TILE_MAP_0` or `TILE_MAP_1`
+    TileMap(u16),
+    Objects,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rendered {
+    Tiles { bank: u8, image: Image },
+    TileMap { map: u16, image: Image },
+    Objects(Vec<ObjectEntry>),
+}
+
+pub fn render(ppu: &Ppu, view: View) -> Rendered {
+    match view {
+        View::Tiles { bank } => Rendered::Tiles {
+            bank,
+            image: tile_sheet(ppu, bank),
+        },
+        View::TileMap(map) => Rendered::TileMap {
+            map,
+            image: tile_map(ppu, map),
+        },
+        View::Objects => Rendered::Objects(objects(ppu)),
+    }
+}
+
+// the number of vram banks worth looking at
+pub fn banks(ppu: &Ppu) -> u8 {
+    if ppu.is_cgb() { 2 } else { 1 }
+}
+
+// all 384 tiles of a vram bank, coloured with BGP on dmg or bg palette 0 on cgb
+pub fn tile_sheet(ppu: &Ppu, bank: u8) -> Image {
+    let rows = TILES / TILES_PER_ROW;
+    let mut image = Image::new(TILES_PER_ROW * 8, rows * 8);
+
+    for index in 0..TILES {
+        let tile = read_tile_in(ppu, bank & 1, 0x8000 + index as u16 * 16);
+        let (left, top) = ((index % TILES_PER_ROW) * 8, (index / TILES_PER_ROW) * 8);
+        for (offset, &color) in tile.iter().enumerate() {
+            image.set(left + offset % 8, top + offset / 8, color);
+        }
+    }
+
+    image
+}
+
+// the whole 256x256 bg map at `map`, using the tile addressing mode and (on cgb) the
+// attributes currently in effect. the part that's on screen is outlined: the viewport
+// if the background uses this map, the window's area if the window does.
+pub fn tile_map(ppu: &Ppu, map: u16) -> Image {
+    let mut image = Image::new(TILE_MAP_SIZE, TILE_MAP_SIZE);
+
+    for entry in 0..32 * 32 {
+        let map_address = map + entry as u16;
+        let (left, top) = ((entry % 32) * 8, (entry / 32) * 8);
+        for row in 0..8 {
+            let (pixels, attributes) = ppu.map_tile_row(map_address, row as u8);
+            for (column, color_index) in pixels.into_iter().enumerate() {
+                image.set(
+                    left + column,
+                    top + row,
+                    ppu.displayable(ppu.bg_color(attributes, color_index)),
+                );
+            }
+        }
+    }
+
+    if map == selected_map(ppu.lcdc, BG_TILE_MAP) {
+        // the viewport wraps around the edges of the map
+        outline(
+            &mut image,
+            ppu.scx as usize,
+            ppu.scy as usize,
+            WIDTH,
+            HEIGHT,
+            VIEWPORT_COLOR,
+        );
+    }
+    if map == selected_map(ppu.lcdc, WINDOW_TILE_MAP)
+        && let Some((skip, width, height)) = window_area(ppu)
+    {
+        outline(&mut image, skip, 0, width, height, WINDOW_COLOR);
+    }
+
+    image
+}
+
+// every entry in oam, in oam order
+pub fn objects(ppu: &Ppu) -> Vec<ObjectEntry> {
+    let height = ppu.object_height() as usize;
+
+    ppu.oam
+        .chunks_exact(4)
+        .take(OBJECTS)
+        .enumerate()
+        .map(|(index, entry)| {
+            let object = Object {
+                y: entry[0],
+                x: entry[1],
+                tile: entry[2],
+                attributes: entry[3],
+                index: index as u8,
+            };
+            let bank = ppu.tile_bank(object.attributes);
+            let palette = if ppu.is_cgb() {
+                object.attributes & CGB_PALETTE
+            } else {
+                (object.attributes & OBJ_PALETTE != 0) as u8
+            };
+            ObjectEntry {
+                object,
+                thumbnail: thumbnail(ppu, &object, height),
+                is_behind_bg: object.attributes & OBJ_BEHIND_BG != 0,
+                is_x_flipped: object.attributes & OBJ_X_FLIP != 0,
+                is_y_flipped: object.attributes & OBJ_Y_FLIP != 0,
+                palette,
+                bank,
+            }
+        })
+        .collect()
+}
+
+fn thumbnail(ppu: &Ppu, object: &Object, height: usize) -> Image {
+    let mut image = Image::new(8, height);
+
+    let tile = if height == 16 {
+        object.tile & 0xFE
+    } else {
+        object.tile
+    };
+    let bank = ppu.tile_bank(object.attributes);
+    for y in 0..height {
+        let row = if object.attributes & OBJ_Y_FLIP != 0 {
+            height - 1 - y
+        } else {
+            y
+        };
+        let mut pixels = ppu.tile_row_in(bank, 0x8000 + tile as u16 * 16, row as u16);
+        if object.attributes & OBJ_X_FLIP != 0 {
+            pixels.reverse();
+        }
+        for (x, color_index) in pixels.into_iter().enumerate() {
+            let color = match color_index {
+                0 => TRANSPARENT,
+                _ => ppu.displayable(ppu.obj_color(object.attributes, color_index)),
+            };
+            image.set(x, y, color);
+        }
+    }
+
+    image
+}
+
+fn selected_map(lcdc: u8, bit: u8) -> u16 {
+    if lcdc & bit != 0 {
+        TILE_MAP_1
+    } else {
+        TILE_MAP_0
+    }
+}
+
+// the part of the window map that makes it onto the screen, if any. WX below 7 cuts off
+// the left side of the window instead of moving it.
+fn window_area(ppu: &Ppu) -> Option<(usize, usize, usize)> {
+    if ppu.lcdc & WINDOW_ENABLE == 0 || ppu.wy as usize >= HEIGHT || ppu.wx > 166 {
+        return None;
+    }
+    let skip = 7usize.saturating_sub(ppu.wx as usize);
+    let left = (ppu.wx as usize).saturating_sub(7);
+    Some((skip, WIDTH - left, HEIGHT - ppu.wy as usize))
+}
+
+// draws the border of a rectangle, wrapping around the edges of the image
+fn outline(
+    image: &mut Image,
+    left: usize,
+    top: usize,
+    width: usize,
+    height: usize,
+    color: RGBA8888,
+) {
+    let (image_width, image_height) = (image.width, image.height);
+    let mut plot = |x: usize, y: usize| image.set(x % image_width, y % image_height, color);
+
+    for x in left..left + width {
+        plot(x, top);
+        plot(x, top + height - 1);
+    }
+    for y in top..top + height {
+        plot(left, y);
+        plot(left + width - 1, y);
+    }
+}
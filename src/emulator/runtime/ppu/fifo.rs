@@ -0,0 +1,372 @@
+use std::collections::VecDeque;
+
+use super::{
+    BG_ENABLE, BG_TILE_MAP, BG_X_FLIP, BG_Y_FLIP, OBJ_ENABLE, OBJ_X_FLIP, OBJ_Y_FLIP, Object, Ppu,
+    RGBA8888, TILE_MAP_0, TILE_MAP_1, WIDTH, WINDOW_ENABLE, WINDOW_TILE_MAP, WindowState,
+};
+
+// the pixel fifo renderer models mode 3 the way the hardware does it: a fetcher reads
+// tiles into a background fifo one dot at a time, and pixels get shifted out of it onto
+// the lcd. registers are read at the moment the fetcher or the lcd needs them, so
+// changing them mid-scanline has the same effect as on hardware. how long mode 3 takes
+// falls out of this as well: fine scroll discards pixels, objects stall the fetcher
+// and the window restarts it.
+
+// every fetcher step except pushing takes two dots
+#[derive(Default, Clone, Copy, PartialEq)]
+enum Step {
+    #[default]
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+#[derive(Default)]
+struct Fetcher {
+    step: Step,
+    // dots spent in the current step
+    dots: u8,
+    // tile column, relative to SCX for the background and to the left edge of the window
+    x: u8,
+    tile: u8,
+    // cgb bg map attributes of `tile`
+    attributes: u8,
+    low: u8,
+    high: u8,
+}
+
+// dots the fetcher takes for a single tile, which is also what fetching an object costs
+const FETCH_DOTS: u8 = 6;
+
+#[derive(Default)]
+pub struct Fifo {
+    fetcher: Fetcher,
+    // colour index and cgb attributes
+    background: VecDeque<(u8, u8)>,
+    // colour index, attributes and oam index, colour 0 being transparent
+    objects: VecDeque<(u8, u8, u8)>,
+    // the next pixel on the lcd
+    x: u8,
+    // pixels to throw away before anything reaches the lcd, from SCX or WX < 7
+    discard: u8,
+    // dots left where the fifo is stalled, e.g. by an object fetch
+    stall: u8,
+    is_in_window: bool,
+    has_drawn_window: bool,
+    // which of the objects found by the oam scan were already fetched
+    fetched_objects: [bool; super::OBJECTS_PER_LINE],
+}
+
+impl Fifo {
+    // resets the fifo at the start of mode 3
+    pub fn start_line(&mut self, ppu: &Ppu) {
+        self.fetcher = Fetcher::default();
+        self.background.clear();
+        self.objects.clear();
+        self.x = 0;
+        self.is_in_window = false;
+        self.has_drawn_window = false;
+        self.fetched_objects = Default::default();
+
+        self.discard = ppu.scx % 8;
+        // the first tile of every line is fetched twice, the first one is thrown away
+        self.stall = FETCH_DOTS;
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.x as usize >= WIDTH
+    }
+
+    pub fn has_drawn_window(&self) -> bool {
+        self.has_drawn_window
+    }
+
+    // runs a single dot of mode 3 and returns the pixel pushed to the lcd, if any
+    pub fn step(&mut self, ppu: &Ppu, window: &WindowState) -> Option<(usize, RGBA8888)> {
+        if self.is_done() {
+            return None;
+        }
+
+        if self.stall > 0 {
+            self.stall -= 1;
+            return None;
+        }
+
+        if !self.is_in_window && self.should_start_window(ppu, window) {
+            // the window throws away everything the fetcher has and starts over
+            self.is_in_window = true;
+            self.has_drawn_window = true;
+            self.background.clear();
+            self.fetcher = Fetcher::default();
+            self.discard = 7u8.saturating_sub(ppu.wx);
+        }
+
+        if let Some(index) = self.next_object(ppu) {
+            // the background fetcher has to finish its current tile before an object
+            // can be fetched
+            if self.fetcher.step != Step::Push {
+                self.step_fetcher(ppu, window);
+                return None;
+            }
+            self.fetch_object(ppu, ppu.objects[index]);
+            self.fetched_objects[index] = true;
+            self.stall = FETCH_DOTS - 1;
+            return None;
+        }
+
+        self.step_fetcher(ppu, window);
+
+        let background = self.background.pop_front()?;
+        if self.discard > 0 {
+            self.discard -= 1;
+            return None;
+        }
+
+        // on dmg, lcdc bit 0 blanks the background and window to colour 0
+        let is_hidden = if self.is_in_window {
+            ppu.layers.hide_window
+        } else {
+            ppu.layers.hide_background
+        };
+        let background = if (ppu.is_cgb() || ppu.lcdc & BG_ENABLE != 0) && !is_hidden {
+            background
+        } else {
+            (0, 0)
+        };
+
+        // hidden objects are still fetched, so mode 3 takes just as long
+        let object = self
+            .objects
+            .pop_front()
+            .filter(|&(color_index, _, _)| color_index != 0 && !ppu.layers.hide_objects)
+            .map(|(color_index, attributes, _)| (color_index, attributes));
+        let color = ppu.mix(background, object);
+
+        let x = self.x as usize;
+        self.x += 1;
+        Some((x, color))
+    }
+
+    fn should_start_window(&self, ppu: &Ppu, window: &WindowState) -> bool {
+        ppu.lcdc & WINDOW_ENABLE != 0
+            && (ppu.is_cgb() || ppu.lcdc & BG_ENABLE != 0)
+            && window.is_triggered
+            && ppu.wx < 166
+            && self.x as u16 + 7 >= ppu.wx as u16
+    }
+
+    // the first object from the oam scan that starts at the current pixel
+    fn next_object(&self, ppu: &Ppu) -> Option<usize> {
+        if ppu.lcdc & OBJ_ENABLE == 0 {
+            return None;
+        }
+        ppu.objects
+            .iter()
+            .enumerate()
+            .find(|(index, object)| {
+                !self.fetched_objects[*index] && object.x as u16 <= self.x as u16 + 8
+            })
+            .map(|(index, _)| index)
+    }
+
+    // mixes an object's pixels into the object fifo. on dmg, pixels that are already
+    // occupied by an earlier object keep it, which is what gives it x coordinate priority.
+    // cgb games let objects earlier in oam take over opaque pixels instead, see OPRI.
+    fn fetch_object(&mut self, ppu: &Ppu, object: Object) {
+        let height = ppu.object_height();
+        let mut row = (ppu.ly as u16 + 16 - object.y as u16) as u8;
+        if object.attributes & OBJ_Y_FLIP != 0 {
+            row = height - 1 - row;
+        }
+        let tile = if height == 16 {
+            object.tile & 0xFE
+        } else {
+            object.tile
+        };
+
+        let mut pixels = ppu.tile_row_in(
+            ppu.tile_bank(object.attributes),
+            0x8000 + tile as u16 * 16,
+            row as u16,
+        );
+        if object.attributes & OBJ_X_FLIP != 0 {
+            pixels.reverse();
+        }
+
+        // objects hanging off the left edge of the screen are cut off
+        let skip = (self.x as usize + 8).saturating_sub(object.x as usize);
+        while self.objects.len() < 8 {
+            self.objects.push_back((0, 0, 0));
+        }
+        for (slot, color_index) in pixels.into_iter().skip(skip).enumerate() {
+            let (current, _, index) = self.objects[slot];
+            let takes_over =
+                current == 0 || (!ppu.has_x_priority() && color_index != 0 && object.index < index);
+            if takes_over {
+                self.objects[slot] = (color_index, object.attributes, object.index);
+            }
+        }
+    }
+
+    fn step_fetcher(&mut self, ppu: &Ppu, window: &WindowState) {
+        let fetcher = &mut self.fetcher;
+
+        if fetcher.step == Step::Push {
+            // the fetcher waits until the fifo is empty before pushing a new tile
+            if !self.background.is_empty() {
+                return;
+            }
+            for column in 0..8 {
+                let column = if fetcher.attributes & BG_X_FLIP != 0 {
+                    7 - column
+                } else {
+                    column
+                };
+                let bit_low = (fetcher.low >> (7 - column)) & 1;
+                let bit_high = (fetcher.high >> (7 - column)) & 1;
+                self.background
+                    .push_back(((bit_high << 1) | bit_low, fetcher.attributes));
+            }
+            fetcher.x = fetcher.x.wrapping_add(1);
+            fetcher.step = Step::Tile;
+            return;
+        }
+
+        fetcher.dots += 1;
+        if fetcher.dots < 2 {
+            return;
+        }
+        fetcher.dots = 0;
+
+        let (row, tile_address) = if self.is_in_window {
+            let map = if ppu.lcdc & WINDOW_TILE_MAP != 0 {
+                TILE_MAP_1
+            } else {
+                TILE_MAP_0
+            };
+            let row = window.line;
+            (row, map + (row / 8) as u16 * 32 + (fetcher.x % 32) as u16)
+        } else {
+            let map = if ppu.lcdc & BG_TILE_MAP != 0 {
+                TILE_MAP_1
+            } else {
+                TILE_MAP_0
+            };
+            let row = ppu.ly.wrapping_add(ppu.scy);
+            let column = ((ppu.scx / 8).wrapping_add(fetcher.x) % 32) as u16;
+            (row, map + (row / 8) as u16 * 32 + column)
+        };
+
+        let row = if fetcher.attributes & BG_Y_FLIP != 0 {
+            7 - row % 8
+        } else {
+            row % 8
+        };
+        let bank = ppu.tile_bank(fetcher.attributes);
+
+        match fetcher.step {
+            Step::Tile => {
+                fetcher.tile = ppu.vram_byte(tile_address);
+                fetcher.attributes = ppu.map_attributes(tile_address);
+                fetcher.step = Step::DataLow;
+            }
+            Step::DataLow => {
+                let addr = ppu.tile_address(fetcher.tile) + row as u16 * 2;
+                fetcher.low = ppu.vram_byte_in(bank, addr);
+                fetcher.step = Step::DataHigh;
+            }
+            Step::DataHigh => {
+                let addr = ppu.tile_address(fetcher.tile) + row as u16 * 2 + 1;
+                fetcher.high = ppu.vram_byte_in(bank, addr);
+                fetcher.step = Step::Push;
+            }
+            Step::Push => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Mode, Renderer};
+    use super::*;
+    use crate::emulator::runtime::bus::device::Device;
+    use crate::emulator::runtime::bus::io::interrupts::Interrupts;
+    use crate::emulator::runtime::model::Model;
+
+    // sets up a fresh dmg with `setup` and returns how many dots mode 3 took on line 1.
+    // line 0 was already scanned before the setup, so it doesn't count.
+    fn mode_3_dots(setup: impl FnOnce(&mut Ppu)) -> usize {
+        let mut ppu = Ppu::new(Model::Dmg);
+        ppu.set_renderer(Renderer::Fifo);
+        setup(&mut ppu);
+        let mut interrupts = Interrupts::default();
+        while ppu.ly() != 1 {
+            ppu.tick(1, &mut interrupts);
+        }
+        let mut dots = 0;
+        while ppu.ly() == 1 {
+            ppu.tick(1, &mut interrupts);
+            dots += (ppu.mode() == Mode::Drawing) as usize;
+        }
+        dots
+    }
+
+    // puts object `index` at the given screen x on line 1 and turns objects on
+    fn place_object(ppu: &mut Ppu, index: u16, x: u8) {
+        let addr = 0xFE00 + index * 4;
+        ppu.poke(addr, 17).unwrap();
+        ppu.poke(addr + 1, x + 8).unwrap();
+        ppu.poke(0xFF40, 0x93).unwrap();
+    }
+
+    // mode 3 with nothing going on: the first tile fetched twice, then a pixel a dot
+    const PLAIN: usize = 172;
+
+    #[test]
+    fn fine_scroll_discards_pixels_at_the_start_of_the_line() {
+        for scx in 0..16 {
+            let dots = mode_3_dots(|ppu| ppu.poke(0xFF43, scx).unwrap());
+            assert_eq!(dots, PLAIN + scx as usize % 8, "SCX {}", scx);
+        }
+    }
+
+    #[test]
+    fn objects_stall_the_fetcher() {
+        // between two tiles the object fetch is all it costs
+        assert_eq!(mode_3_dots(|ppu| place_object(ppu, 0, 0)), PLAIN + 6);
+        // a dot later the fetcher has just started on a tile, which it finishes first
+        assert_eq!(mode_3_dots(|ppu| place_object(ppu, 0, 1)), PLAIN + 12);
+        assert_eq!(mode_3_dots(|ppu| place_object(ppu, 0, 7)), PLAIN + 6);
+
+        // objects on the same pixel are fetched one after the other
+        let dots = mode_3_dots(|ppu| {
+            place_object(ppu, 0, 0);
+            place_object(ppu, 1, 0);
+        });
+        assert_eq!(dots, PLAIN + 12);
+
+        // with objects off, nothing gets fetched
+        let dots = mode_3_dots(|ppu| {
+            place_object(ppu, 0, 0);
+            ppu.poke(0xFF40, 0x91).unwrap();
+        });
+        assert_eq!(dots, PLAIN);
+    }
+
+    #[test]
+    fn the_window_restarts_the_fetcher() {
+        let window_at = |wx| {
+            mode_3_dots(|ppu| {
+                ppu.poke(0xFF40, 0x91 | WINDOW_ENABLE).unwrap();
+                ppu.poke(0xFF4A, 1).unwrap();
+                ppu.poke(0xFF4B, wx).unwrap();
+            })
+        };
+        // the fetcher throws away what it has and fetches the window's first tile
+        assert_eq!(window_at(87), PLAIN + 6);
+        assert_eq!(window_at(8), PLAIN + 6);
+        // at WX 166 it's off screen, and on this line it never starts
+        assert_eq!(window_at(166), PLAIN);
+    }
+}
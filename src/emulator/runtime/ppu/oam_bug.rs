@@ -0,0 +1,167 @@
+// the dmg scrambles oam when the cpu touches 0xFE00-0xFEFF while the ppu is scanning it.
+// oam is read as 20 rows of 8 bytes, one row per m-cycle, and the row the ppu is on gets
+// mixed with the one before it. the patterns are made up of 16 bit words.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corruption {
+    Read,
+    // also what the 16 bit inc/dec unit causes on its own
+    Write,
+    // a read and an increment or decrement in the same m-cycle, like LD A, [HL+]
+    ReadDuringIncDec,
+}
+
+const ROW_SIZE: usize = 8;
+const ROWS: usize = 20;
+
+fn word(oam: &[u8], row: usize, index: usize) -> u16 {
+    let offset = row * ROW_SIZE + index * 2;
+    u16::from_le_bytes([oam[offset], oam[offset + 1]])
+}
+
+fn set_word(oam: &mut [u8], row: usize, index: usize, value: u16) {
+    let offset = row * ROW_SIZE + index * 2;
+    oam[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+// copies the last three words of a row
+fn copy_tail(oam: &mut [u8], from: usize, to: usize) {
+    oam.copy_within(
+        from * ROW_SIZE + 2..(from + 1) * ROW_SIZE,
+        to * ROW_SIZE + 2,
+    );
+}
+
+// applies `kind` to the row the ppu is currently reading. the first row has no row
+// before it and is left alone.
+pub fn corrupt(oam: &mut [u8], row: usize, kind: Corruption) {
+    if row == 0 || row >= ROWS {
+        return;
+    }
+
+    match kind {
+        Corruption::Write => {
+            let a = word(oam, row, 0);
+            let b = word(oam, row - 1, 0);
+            let c = word(oam, row - 1, 2);
+            set_word(oam, row, 0, ((a ^ c) & (b ^ c)) ^ c);
+            copy_tail(oam, row - 1, row);
+        }
+        Corruption::Read => {
+            let a = word(oam, row, 0);
+            let b = word(oam, row - 1, 0);
+            let c = word(oam, row - 1, 2);
+            set_word(oam, row, 0, b | (a & c));
+            copy_tail(oam, row - 1, row);
+        }
+        Corruption::ReadDuringIncDec => {
+            // this part skips the first four rows and the last one
+            if (4..ROWS - 1).contains(&row) {
+                let a = word(oam, row - 2, 0);
+                let b = word(oam, row - 1, 0);
+                let c = word(oam, row, 0);
+                let d = word(oam, row - 1, 2);
+                set_word(oam, row - 1, 0, (b & (a | c | d)) | (a & c & d));
+
+                let previous = (row - 1) * ROW_SIZE;
+                oam.copy_within(previous..previous + ROW_SIZE, row * ROW_SIZE);
+                oam.copy_within(previous..previous + ROW_SIZE, (row - 2) * ROW_SIZE);
+            }
+            corrupt(oam, row, Corruption::Read);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every word holds its own row and index, so copies are easy to spot
+    fn oam() -> [u8; 0xA0] {
+        let mut oam = [0; 0xA0];
+        for row in 0..ROWS {
+            for index in 0..4 {
+                set_word(&mut oam, row, index, ((row as u16) << 8) | index as u16);
+            }
+        }
+        oam
+    }
+
+    fn row(oam: &[u8], row: usize) -> [u16; 4] {
+        std::array::from_fn(|index| word(oam, row, index))
+    }
+
+    #[test]
+    fn write_mixes_the_first_word_and_copies_the_rest() {
+        let mut oam = oam();
+        set_word(&mut oam, 4, 0, 0x00F0);
+        set_word(&mut oam, 4, 2, 0x0FF0);
+        set_word(&mut oam, 5, 0, 0x3C3C);
+        corrupt(&mut oam, 5, Corruption::Write);
+
+        // ((a ^ c) & (b ^ c)) ^ c
+        assert_eq!(row(&oam, 5), [0x0CF0, 0x0401, 0x0FF0, 0x0403]);
+        assert_eq!(row(&oam, 4), [0x00F0, 0x0401, 0x0FF0, 0x0403]);
+        assert_eq!(row(&oam, 6), [0x0600, 0x0601, 0x0602, 0x0603]);
+    }
+
+    #[test]
+    fn read_mixes_the_first_word_and_copies_the_rest() {
+        let mut oam = oam();
+        set_word(&mut oam, 4, 0, 0x00F0);
+        set_word(&mut oam, 4, 2, 0x0FF0);
+        set_word(&mut oam, 5, 0, 0x3C3C);
+        corrupt(&mut oam, 5, Corruption::Read);
+
+        // b | (a & c)
+        assert_eq!(row(&oam, 5), [0x0CF0, 0x0401, 0x0FF0, 0x0403]);
+
+        let mut oam = self::oam();
+        set_word(&mut oam, 4, 0, 0x8001);
+        corrupt(&mut oam, 5, Corruption::Read);
+        assert_eq!(
+            row(&oam, 5),
+            [0x8001 | (0x0500 & 0x0402), 0x0401, 0x0402, 0x0403]
+        );
+    }
+
+    #[test]
+    fn read_during_inc_dec_spreads_the_row_before() {
+        let mut oam = oam();
+        corrupt(&mut oam, 6, Corruption::ReadDuringIncDec);
+
+        // (b & (a | c | d)) | (a & c & d), then copied over the rows around it
+        let first = (0x0500 & (0x0400 | 0x0600 | 0x0502)) | (0x0400 & 0x0600 & 0x0502);
+        let mixed = [first, 0x0501, 0x0502, 0x0503];
+        assert_eq!(row(&oam, 4), mixed);
+        assert_eq!(row(&oam, 5), mixed);
+        assert_eq!(row(&oam, 6), mixed);
+        assert_eq!(row(&oam, 3), [0x0300, 0x0301, 0x0302, 0x0303]);
+        assert_eq!(row(&oam, 7), [0x0700, 0x0701, 0x0702, 0x0703]);
+    }
+
+    #[test]
+    fn read_during_inc_dec_is_a_plain_read_near_the_edges() {
+        for target in [1, 2, 3, ROWS - 1] {
+            let mut expected = oam();
+            corrupt(&mut expected, target, Corruption::Read);
+            let mut oam = oam();
+            corrupt(&mut oam, target, Corruption::ReadDuringIncDec);
+            assert_eq!(oam, expected, "row {}", target);
+        }
+    }
+
+    #[test]
+    fn the_first_row_is_left_alone() {
+        for kind in [
+            Corruption::Read,
+            Corruption::Write,
+            Corruption::ReadDuringIncDec,
+        ] {
+            let mut oam = oam();
+            corrupt(&mut oam, 0, kind);
+            corrupt(&mut oam, ROWS, kind);
+            assert_eq!(oam, self::oam());
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use super::{FrameBuffer, HEIGHT, Ppu, RGBA8888, WIDTH, WINDOW_ENABLE};
+
+// debug information drawn on top of published frames. the frame the ppu draws into is left
+// alone, overlays only end up in the copy that goes out to the frontend.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Overlays {
+    // outlines every object that's on screen, labelled with its oam index
+    pub object_boxes: bool,
+    // marks where the window's top left corner is
+    pub window_origin: bool,
+    // highlights the scanline the ppu is on when emulation pauses mid frame
+    pub scanline: bool,
+}
+
+impl Overlays {
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::default()
+    }
+}
+
+pub const OBJECT_COLOR: RGBA8888 = 0xFF00FFFF;
+pub const WINDOW_COLOR: RGBA8888 = 0x0000FFFF;
+const SCANLINE_COLOR: RGBA8888 = 0xFF000000;
+
+// 3x5 digits for the oam index labels, one row per byte, most significant of the low three
+// bits on the left
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b011, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+// draws the enabled overlays onto `frame`. `scanline` is the line to highlight, if the
+// frame is only partially drawn.
+pub fn draw(ppu: &Ppu, frame: &mut FrameBuffer, scanline: Option<u8>) {
+    let overlays = ppu.overlays;
+
+    if overlays.object_boxes {
+        draw_object_boxes(ppu, frame);
+    }
+    if overlays.window_origin {
+        draw_window_origin(ppu, frame);
+    }
+    if overlays.scanline
+        && let Some(ly) = scanline.filter(|&ly| (ly as usize) < HEIGHT)
+    {
+        let line = &mut frame[ly as usize * WIDTH..(ly as usize + 1) * WIDTH];
+        for pixel in line {
+            *pixel = blend(*pixel, SCANLINE_COLOR);
+        }
+    }
+}
+
+fn draw_object_boxes(ppu: &Ppu, frame: &mut FrameBuffer) {
+    let height = ppu.object_height() as i32;
+
+    for (index, entry) in ppu.oam.chunks_exact(4).enumerate() {
+        // oam coordinates are those of the bottom right corner of an 8x16 object
+        let left = entry[1] as i32 - 8;
+        let top = entry[0] as i32 - 16;
+        if left <= -8 || left >= WIDTH as i32 || top <= -height || top >= HEIGHT as i32 {
+            continue;
+        }
+
+        for x in left..left + 8 {
+            plot(frame, x, top, OBJECT_COLOR);
+            plot(frame, x, top + height - 1, OBJECT_COLOR);
+        }
+        for y in top..top + height {
+            plot(frame, left, y, OBJECT_COLOR);
+            plot(frame, left + 7, y, OBJECT_COLOR);
+        }
+        draw_number(frame, left + 1, top + 1, index);
+    }
+}
+
+fn draw_window_origin(ppu: &Ppu, frame: &mut FrameBuffer) {
+    if ppu.lcdc & WINDOW_ENABLE == 0 {
+        return;
+    }
+
+    // a small cross, which stays visible even when the origin is off screen on one axis
+    let x = ppu.wx as i32 - 7;
+    let y = ppu.wy as i32;
+    for offset in -3..=3 {
+        plot(frame, x + offset, y, WINDOW_COLOR);
+        plot(frame, x, y + offset, WINDOW_COLOR);
+    }
+}
+
+fn draw_number(frame: &mut FrameBuffer, left: i32, top: i32, number: usize) {
+    let digits = if number >= 10 {
+        vec![number / 10, number % 10]
+    } else {
+        vec![number]
+    };
+
+    for (position, digit) in digits.into_iter().enumerate() {
+        let digit_left = left + position as i32 * 4;
+        for (row, bits) in DIGITS[digit].iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) != 0 {
+                    plot(frame, digit_left + column, top + row as i32, OBJECT_COLOR);
+                }
+            }
+        }
+    }
+}
+
+// sets a pixel, unless it's off screen
+fn plot(frame: &mut FrameBuffer, x: i32, y: i32, color: RGBA8888) {
+    if (0..WIDTH as i32).contains(&x) && (0..HEIGHT as i32).contains(&y) {
+        frame[y as usize * WIDTH + x as usize] = color;
+    }
+}
+
+// averages two colours, keeping the result opaque
+fn blend(a: RGBA8888, b: RGBA8888) -> RGBA8888 {
+    (((a >> 1) & 0x7F7F7F00) + ((b >> 1) & 0x7F7F7F00)) | 0xFF
+}
@@ -0,0 +1,311 @@
+use std::fmt;
+use std::str::FromStr;
+
+use catppuccin::{Color, Flavor, PALETTE};
+
+use super::{BG_PALETTE, RGBA8888};
+
+pub mod colorization;
+
+// the four colours a dmg shade (white, light gray, dark gray, black) is displayed as
+pub type Palette = [RGBA8888; 4];
+
+// the background and both object palettes can be coloured independently, the same way
+// the cgb colourises dmg games
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palettes {
+    pub bg: Palette,
+    pub obj0: Palette,
+    pub obj1: Palette,
+}
+
+impl Palettes {
+    pub fn uniform(palette: Palette) -> Self {
+        Self {
+            bg: palette,
+            obj0: palette,
+            obj1: palette,
+        }
+    }
+}
+
+impl Default for Palettes {
+    fn default() -> Self {
+        Self::uniform(Preset::ClassicGreen.palette())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    ClassicGreen,
+    Grey,
+    Pocket,
+    Latte,
+    Frappe,
+    Macchiato,
+    Mocha,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 7] = [
+        Preset::ClassicGreen,
+        Preset::Grey,
+        Preset::Pocket,
+        Preset::Latte,
+        Preset::Frappe,
+        Preset::Macchiato,
+        Preset::Mocha,
+    ];
+
+    pub fn palette(self) -> Palette {
+        match self {
+            Preset::ClassicGreen => BG_PALETTE,
+            Preset::Grey => [0xFFFFFFFF, 0xAAAAAAFF, 0x555555FF, 0x000000FF],
+            Preset::Pocket => [0xC4CFA1FF, 0x8B956DFF, 0x4D533CFF, 0x1F1F1FFF],
+            Preset::Latte => from_flavor(PALETTE.latte),
+            Preset::Frappe => from_flavor(PALETTE.frappe),
+            Preset::Macchiato => from_flavor(PALETTE.macchiato),
+            Preset::Mocha => from_flavor(PALETTE.mocha),
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Preset::ClassicGreen => "green",
+            Preset::Grey => "grey",
+            Preset::Pocket => "pocket",
+            Preset::Latte => "latte",
+            Preset::Frappe => "frappe",
+            Preset::Macchiato => "macchiato",
+            Preset::Mocha => "mocha",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Preset::ALL
+            .into_iter()
+            .find(|preset| preset.to_string() == s)
+            .ok_or_else(|| format!("unknown palette preset {:?}", s))
+    }
+}
+
+// games expect shade 0 to be the lightest, so light flavours go from the background
+// to the text colour and dark flavours the other way around
+fn from_flavor(flavor: Flavor) -> Palette {
+    let colors = flavor.colors;
+    let shades = if flavor.dark {
+        [colors.text, colors.overlay1, colors.surface1, colors.crust]
+    } else {
+        [colors.base, colors.surface2, colors.overlay2, colors.text]
+    };
+    shades.map(rgba_from_catppuccin)
+}
+
+fn rgba_from_catppuccin(color: Color) -> RGBA8888 {
+    let rgb = color.rgb;
+    (rgb.r as u32) << 24 | (rgb.g as u32) << 16 | (rgb.b as u32) << 8 | 0xFF
+}
+
+// parses user defined palettes. every line assigns four colours, lightest first, to the
+// background, one of the object palettes or all of them at once:
+//
+//     all = E0F8D0 88C070 346856 081820
+//     obj1 = FFFFFF FF8484 943A3A 000000
+//
+// lines may also name a preset instead (`bg = mocha`). empty lines and lines starting
+// with `#` are ignored.
+pub fn parse_palettes(config: &str) -> Result<Palettes, String> {
+    let mut palettes = Palettes::default();
+
+    for (number, line) in config.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error = |reason: &str| format!("line {}: {}", number + 1, reason);
+
+        let (target, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected `<palette> = <colours>`"))?;
+        let value = value.trim();
+
+        let palette = match value.parse::<Preset>() {
+            Ok(preset) => preset.palette(),
+            Err(_) => parse_palette(value).map_err(|reason| error(&reason))?,
+        };
+
+        match target.trim() {
+            "all" => palettes = Palettes::uniform(palette),
+            "bg" => palettes.bg = palette,
+            "obj0" => palettes.obj0 = palette,
+            "obj1" => palettes.obj1 = palette,
+            other => return Err(error(&format!("unknown palette {:?}", other))),
+        }
+    }
+
+    Ok(palettes)
+}
+
+fn parse_palette(value: &str) -> Result<Palette, String> {
+    let colors = value
+        .split_whitespace()
+        .map(|hex| {
+            let hex = hex.trim_start_matches('#');
+            if hex.len() != 6 {
+                return Err(format!("expected a colour like E0F8D0, got {:?}", hex));
+            }
+            u32::from_str_radix(hex, 16)
+                .map(|rgb| (rgb << 8) | 0xFF)
+                .map_err(|e| format!("invalid colour {:?}: {}", hex, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    colors
+        .try_into()
+        .map_err(|colors: Vec<_>| format!("expected 4 colours, got {}", colors.len()))
+}
+
+// cgb colour palette memory: 8 palettes of 4 colours, each colour being two bytes of
+// little endian rgb555. the cpu goes through it one byte at a time via an index
+// register, which optionally increments itself after every write to the data register.
+#[derive(Clone)]
+pub struct PaletteRam {
+    bytes: [u8; 64],
+    // bits 0-5 are the index, bit 7 turns on auto increment
+    spec: u8,
+}
+
+const AUTO_INCREMENT: u8 = 1 << 7;
+
+impl Default for PaletteRam {
+    // starts out all white, which is what the cgb boot rom leaves behind
+    fn default() -> Self {
+        Self {
+            bytes: [0xFF; 64],
+            spec: 0,
+        }
+    }
+}
+
+impl PaletteRam {
+    pub fn read_spec(&self) -> u8 {
+        // bit 6 is unused and reads as 1
+        self.spec | 0b0100_0000
+    }
+
+    pub fn write_spec(&mut self, content: u8) {
+        self.spec = content & 0b1011_1111;
+    }
+
+    pub fn read_data(&self) -> u8 {
+        self.bytes[(self.spec & 0x3F) as usize]
+    }
+
+    // writes at the current index without advancing it, see `write_data`
+    pub fn poke_data(&mut self, content: u8) {
+        self.bytes[(self.spec & 0x3F) as usize] = content;
+    }
+
+    pub fn write_data(&mut self, content: u8) {
+        self.poke_data(content);
+        if self.spec & AUTO_INCREMENT != 0 {
+            self.spec = AUTO_INCREMENT | (self.spec + 1) & 0x3F;
+        }
+    }
+
+    pub fn color(&self, palette: u8, color_index: u8) -> RGBA8888 {
+        let offset = palette as usize * 8 + color_index as usize * 2;
+        rgba_from_rgb555(u16::from_le_bytes([
+            self.bytes[offset],
+            self.bytes[offset + 1],
+        ]))
+    }
+}
+
+// widens the 5 bit channels by repeating their top bits, so 0x1F ends up as 0xFF
+pub fn rgba_from_rgb555(color: u16) -> RGBA8888 {
+    let channel = |shift: u16| {
+        let value = ((color >> shift) & 0x1F) as u32;
+        (value << 3) | (value >> 2)
+    };
+    channel(0) << 24 | channel(5) << 16 | channel(10) << 8 | 0xFF
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Palette = [0xFFFFFFFF, 0xFF8484FF, 0x943A3AFF, 0x000000FF];
+
+    #[test]
+    fn empty_config_keeps_the_defaults() {
+        let config = "\n   \n# nothing but comments\n";
+        assert_eq!(parse_palettes(config), Ok(Palettes::default()));
+    }
+
+    #[test]
+    fn all_sets_every_palette() {
+        let palettes = parse_palettes("all = FFFFFF FF8484 943A3A 000000").unwrap();
+        assert_eq!(palettes, Palettes::uniform(RED));
+    }
+
+    #[test]
+    fn palettes_are_set_individually_and_in_order() {
+        let config = "all = grey\nobj1 = #FFFFFF #FF8484 #943A3A #000000\n  bg=mocha  ";
+        let palettes = parse_palettes(config).unwrap();
+        assert_eq!(palettes.bg, Preset::Mocha.palette());
+        assert_eq!(palettes.obj0, Preset::Grey.palette());
+        assert_eq!(palettes.obj1, RED);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let config = "# header\nbg = green\nobj2 = green";
+        assert_eq!(
+            parse_palettes(config),
+            Err("line 3: unknown palette \"obj2\"".to_string())
+        );
+        assert!(
+            parse_palettes("bg green")
+                .unwrap_err()
+                .starts_with("line 1:")
+        );
+    }
+
+    #[test]
+    fn malformed_colours_are_rejected() {
+        assert_eq!(
+            parse_palettes("bg = FFFFFF 000000"),
+            Err("line 1: expected 4 colours, got 2".to_string())
+        );
+        assert!(parse_palettes("bg = FFFFFF 000000 000000 FFF").is_err());
+        assert!(parse_palettes("bg = FFFFFF 000000 000000 GGGGGG").is_err());
+        assert!(parse_palettes("bg = FFFFFF 000000 000000 000000 000000").is_err());
+    }
+
+    #[test]
+    fn presets_round_trip_through_their_names() {
+        for preset in Preset::ALL {
+            assert_eq!(preset.to_string().parse::<Preset>(), Ok(preset));
+        }
+        assert!("purple".parse::<Preset>().is_err());
+    }
+
+    #[test]
+    fn rgb555_channels_are_widened() {
+        assert_eq!(rgba_from_rgb555(0x7FFF), 0xFFFFFFFF);
+        assert_eq!(rgba_from_rgb555(0x0000), 0x000000FF);
+        // red is the low 5 bits, blue the high ones
+        assert_eq!(rgba_from_rgb555(0x001F), 0xFF0000FF);
+        assert_eq!(rgba_from_rgb555(0x7C00), 0x0000FFFF);
+        assert_eq!(rgba_from_rgb555(0x0010), 0x840000FF);
+    }
+}
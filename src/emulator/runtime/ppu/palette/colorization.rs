@@ -0,0 +1,260 @@
+use super::{Palette, Palettes, rgba_from_rgb555};
+
+// the cgb boot rom colours dmg games by looking up a checksum of their title in a table.
+// only games published by nintendo are looked up, everything else gets the first
+// combination of palettes, which is also what holding right on the logo screen picks.
+// the tables below are the boot rom's own, just spelled out.
+
+// every colour the boot rom knows about, as rgb555, in groups of four
+const COLORS: [u16; 30 * 4] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000, // 0
+    0x639F, 0x4279, 0x15B0, 0x04CB, // 1
+    0x7FFF, 0x6E31, 0x454A, 0x0000, // 2
+    0x7FFF, 0x1BEF, 0x0200, 0x0000, // 3
+    0x7FFF, 0x421F, 0x1CF2, 0x0000, // 4
+    0x7FFF, 0x5294, 0x294A, 0x0000, // 5
+    0x7FFF, 0x03FF, 0x012F, 0x0000, // 6
+    0x7FFF, 0x03EF, 0x01D6, 0x0000, // 7
+    0x7FFF, 0x42B5, 0x3DC8, 0x0000, // 8
+    0x7E74, 0x03FF, 0x0180, 0x0000, // 9
+    0x67FF, 0x77AC, 0x1A13, 0x2D6B, // 10
+    0x7ED6, 0x4BFF, 0x2175, 0x0000, // 11
+    0x53FF, 0x4A5F, 0x7E52, 0x0000, // 12
+    0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0, // 13
+    0x03ED, 0x7FFF, 0x255F, 0x0000, // 14
+    0x036A, 0x021F, 0x03FF, 0x7FFF, // 15
+    0x7FFF, 0x01DF, 0x0112, 0x0000, // 16
+    0x231F, 0x035F, 0x00F2, 0x0009, // 17
+    0x7FFF, 0x03EA, 0x011F, 0x0000, // 18
+    0x299F, 0x001A, 0x000C, 0x0000, // 19
+    0x7FFF, 0x027F, 0x001F, 0x0000, // 20
+    0x7FFF, 0x03E0, 0x0206, 0x0120, // 21
+    0x7FFF, 0x7EEB, 0x001F, 0x7C00, // 22
+    0x7FFF, 0x3FFF, 0x7E00, 0x001F, // 23
+    0x7FFF, 0x03FF, 0x001F, 0x0000, // 24
+    0x03FF, 0x001F, 0x000C, 0x0000, // 25
+    0x7FFF, 0x033F, 0x0193, 0x0000, // 26
+    0x0000, 0x4200, 0x037F, 0x7FFF, // 27
+    0x7FFF, 0x7E8C, 0x7C00, 0x0000, // 28
+    0x7FFF, 0x1BEF, 0x6180, 0x0000, // 29
+];
+
+// where obj0, obj1 and the background take their four colours from, as an index into
+// `COLORS`. most combinations use whole palettes, but a few start in the middle of one.
+const COMBINATIONS: [[usize; 3]; 51] = [
+    palettes(4, 4, 29),
+    palettes(18, 18, 18),
+    palettes(20, 20, 20),
+    palettes(24, 24, 24),
+    palettes(9, 9, 9),
+    palettes(0, 0, 0),
+    palettes(27, 27, 27),
+    palettes(5, 5, 5),
+    palettes(12, 12, 12),
+    palettes(26, 26, 26),
+    palettes(16, 8, 8), // 10
+    palettes(4, 28, 28),
+    palettes(4, 2, 2),
+    palettes(3, 4, 4),
+    palettes(4, 29, 29),
+    palettes(28, 4, 28),
+    palettes(2, 17, 2),
+    palettes(16, 16, 8),
+    palettes(4, 4, 7),
+    palettes(4, 4, 18),
+    palettes(4, 4, 20), // 20
+    palettes(19, 19, 9),
+    [4 * 4 - 1, 4 * 4 - 1, 11 * 4],
+    palettes(17, 17, 2),
+    palettes(4, 4, 2),
+    palettes(4, 4, 3),
+    palettes(28, 28, 0),
+    palettes(3, 3, 0),
+    palettes(0, 0, 1),
+    palettes(18, 22, 18),
+    palettes(20, 22, 20), // 30
+    palettes(24, 22, 24),
+    palettes(16, 22, 8),
+    palettes(17, 4, 13),
+    [28 * 4 - 1, 0, 14 * 4],
+    [28 * 4 - 1, 4 * 4, 15 * 4],
+    palettes(19, 22, 9),
+    palettes(16, 28, 10),
+    palettes(4, 23, 28),
+    palettes(17, 22, 2),
+    palettes(4, 0, 2), // 40
+    palettes(4, 28, 3),
+    palettes(28, 3, 0),
+    palettes(3, 28, 4),
+    palettes(21, 28, 4),
+    palettes(3, 28, 0),
+    palettes(25, 3, 28),
+    palettes(0, 28, 8),
+    palettes(4, 3, 28),
+    palettes(28, 3, 6),
+    palettes(4, 28, 29), // 50
+];
+
+const fn palettes(obj0: usize, obj1: usize, bg: usize) -> [usize; 3] {
+    [obj0 * 4, obj1 * 4, bg * 4]
+}
+
+// title checksums the boot rom knows. the ones from `FIRST_SHARED` on belong to more
+// than one game, those are told apart by the fourth letter of the title.
+const CHECKSUMS: [u8; 79] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, //
+    // shared
+    0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4,
+];
+
+const FIRST_SHARED: usize = 65;
+
+// letters for the shared checksums, in rows of one letter per checksum. a match in
+// row `n` picks the entry `n` rows of shared checksums past `FIRST_SHARED`.
+const FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+// the combination each checksum (and each letter of the shared ones) ends up with
+const COMBINATION_PER_CHECKSUM: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, //
+    19, 36, 7, 37, 30, 44, 21, 32, 31, 20, //
+    5, 33, 13, 14, 5, 29, 5, 18, 9, 3, //
+    2, 26, 25, 25, 41, 42, 26, 45, 42, 45, //
+    36, 38, 26, 42, 30, 41, 34, 34, 5, 42, //
+    6, 5, 33, 25, 42, 42, 40, 2, 16, 25, //
+    42, 42, 5, 0, 39, 36, 22, 25, 6, 32, //
+    12, 36, 11, 39, 18, 39, 24, 31, 50, 17, //
+    46, 6, 27, 0, 47, 41, 41, 0, 0, 19, //
+    34, 23, 18, 29,
+];
+
+pub fn palettes_for(rom: &[u8]) -> Palettes {
+    let combination = rom
+        .get(0x0134..0x0144)
+        .filter(|_| is_published_by_nintendo(rom))
+        .and_then(combination_for)
+        .unwrap_or(0);
+    let [obj0, obj1, bg] = COMBINATIONS[combination].map(palette_at);
+    Palettes { bg, obj0, obj1 }
+}
+
+fn combination_for(title: &[u8]) -> Option<usize> {
+    let checksum = title.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    let index = CHECKSUMS.iter().position(|&sum| sum == checksum)?;
+    if index < FIRST_SHARED {
+        return Some(COMBINATION_PER_CHECKSUM[index] as usize);
+    }
+
+    let shared = CHECKSUMS.len() - FIRST_SHARED;
+    let row = FOURTH_LETTERS
+        .iter()
+        .skip(index - FIRST_SHARED)
+        .step_by(shared)
+        .position(|&letter| letter == title[3])?;
+    Some(COMBINATION_PER_CHECKSUM[index + row * shared] as usize)
+}
+
+fn palette_at(offset: usize) -> Palette {
+    std::array::from_fn(|i| rgba_from_rgb555(COLORS[offset + i]))
+}
+
+// the old licensee code, or the new one if the old one says so
+fn is_published_by_nintendo(rom: &[u8]) -> bool {
+    match rom.get(0x014B) {
+        Some(0x01) => true,
+        Some(0x33) => rom.get(0x0144..0x0146) == Some(b"01"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: u32 = 0xFFFFFFFF;
+    const BLACK: u32 = 0x000000FF;
+    const RED: Palette = [WHITE, 0xFF8484FF, 0x943939FF, BLACK];
+    const GREEN: Palette = [WHITE, 0x7BFF31FF, 0x008400FF, BLACK];
+    const BLUE: Palette = [WHITE, 0x63A5FFFF, 0x0000FFFF, BLACK];
+
+    fn rom(title: &str, licensee: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title.as_bytes());
+        rom[0x014B] = licensee;
+        rom
+    }
+
+    fn default() -> Palettes {
+        Palettes {
+            bg: [WHITE, 0x7BFF31FF, 0x0063C6FF, BLACK],
+            obj0: RED,
+            obj1: RED,
+        }
+    }
+
+    #[test]
+    fn tables_line_up() {
+        assert_eq!(
+            COMBINATION_PER_CHECKSUM.len(),
+            FIRST_SHARED + FOURTH_LETTERS.len()
+        );
+        for combination in COMBINATIONS.iter().flatten() {
+            assert!(combination + 4 <= COLORS.len());
+        }
+        for &combination in &COMBINATION_PER_CHECKSUM {
+            assert!((combination as usize) < COMBINATIONS.len());
+        }
+    }
+
+    #[test]
+    fn other_publishers_get_the_default() {
+        assert_eq!(palettes_for(&rom("POKEMON RED", 0x00)), default());
+        assert_eq!(palettes_for(&[]), default());
+    }
+
+    #[test]
+    fn unique_checksums_are_looked_up_directly() {
+        let palettes = palettes_for(&rom("POKEMON RED", 0x01));
+        assert_eq!(
+            palettes,
+            Palettes {
+                bg: RED,
+                obj0: GREEN,
+                obj1: RED,
+            }
+        );
+        // the new licensee code works too
+        let mut tetris = rom("TETRIS", 0x33);
+        tetris[0x0144..0x0146].copy_from_slice(b"01");
+        assert_ne!(palettes_for(&tetris), default());
+    }
+
+    #[test]
+    fn shared_checksums_go_by_the_fourth_letter() {
+        let palettes = palettes_for(&rom("POKEMON BLUE", 0x01));
+        assert_eq!(
+            palettes,
+            Palettes {
+                bg: BLUE,
+                obj0: RED,
+                obj1: BLUE,
+            }
+        );
+        // same checksum, a letter that isn't in the table
+        let mut unknown = rom("POKEMON BLUE", 0x01);
+        unknown[0x0137] = b'Z';
+        unknown[0x0138] = b'M' + b'E' - b'Z';
+        assert_eq!(palettes_for(&unknown), default());
+    }
+
+    #[test]
+    fn combinations_can_start_in_the_middle_of_a_palette() {
+        let palettes = palettes_for(&rom("SUPER MARIOLAND", 0x01));
+        assert_eq!(palettes.obj0, [BLACK, WHITE, 0xFF8484FF, 0x943939FF]);
+        assert_eq!(palettes.obj1, palettes.obj0);
+        assert_eq!(palettes.bg, palette_at(11 * 4));
+    }
+}
@@ -0,0 +1,567 @@
+use crate::emulator::host::triple_buffer::Writer;
+use crate::emulator::runtime::ppu::palette::{Palette, rgba_from_rgb555};
+use crate::emulator::runtime::ppu::{self, FrameBuffer, HEIGHT, RGBA8888, WIDTH};
+
+// the super game boy runs a dmg inside a snes. the game talks to it by sending packets
+// through the joypad register (see `Joypad`), which set up palettes for regions of the
+// screen and a border around it. the sgb only ever sees the four shades the lcd would
+// show, which it then colours in.
+
+pub const BORDER_WIDTH: usize = 256;
+pub const BORDER_HEIGHT: usize = 224;
+// where the lcd image sits within the border
+const LCD_X: usize = 48;
+const LCD_Y: usize = 40;
+
+pub type SgbFrameBuffer = Box<[RGBA8888; BORDER_WIDTH * BORDER_HEIGHT]>;
+
+pub fn new_buffer() -> SgbFrameBuffer {
+    Box::new([0u32; BORDER_WIDTH * BORDER_HEIGHT])
+}
+
+// the ppu's palettes hand out these instead of colours in sgb mode, which the sgb keeps
+// track of and colours in, see `Sgb::draw`
+pub const SHADES: Palette = [0, 1, 2, 3];
+
+pub const PACKET_SIZE: usize = 16;
+pub type Packet = [u8; PACKET_SIZE];
+
+// command ids, the upper 5 bits of the first byte of a packet
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+pub const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const MASK_EN: u8 = 0x17;
+
+pub fn command_of(packet: &Packet) -> u8 {
+    packet[0] >> 3
+}
+
+// the attribute commands assign palettes to cells of 8x8 pixels
+const CELLS_X: usize = 20;
+const CELLS_Y: usize = 18;
+
+// size of the data CHR_TRN and PCT_TRN copy off the screen
+pub const TRANSFER_SIZE: usize = 0x1000;
+
+// what the sgb boot rom starts out with
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mask {
+    None,
+    // keeps showing the last frame, so games can redraw things without it being seen
+    Freeze,
+    Black,
+    Color0,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    // border tiles 0x00-0x7F, or 0x80-0xFF if set
+    Tiles(bool),
+    // border tile map and palettes
+    Border,
+}
+
+pub struct Sgb {
+    // packets of a command that spans several of them, until it's complete
+    command: Vec<u8>,
+    packets_left: u8,
+
+    palettes: [[u16; 4]; 4],
+    // palette of every cell of the lcd
+    attributes: [u8; CELLS_X * CELLS_Y],
+    mask: Mask,
+
+    // transfers copy what's on screen during the next frame
+    transfer: Option<Transfer>,
+    // 256 snes tiles, 4 bits per pixel
+    border_tiles: Box<[u8]>,
+    // 32x28 entries of 2 bytes
+    border_map: Box<[u8]>,
+    border_palettes: [[u16; 16]; 4],
+
+    // what the ppu drew, before colouring it in
+    shades: Box<[u8]>,
+    // the last coloured lcd image, for MASK_EN freezing
+    lcd: FrameBuffer,
+    frame: SgbFrameBuffer,
+    writer: Option<Writer<SgbFrameBuffer>>,
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Self {
+            command: Vec::with_capacity(7 * PACKET_SIZE),
+            packets_left: 0,
+            palettes: [DEFAULT_PALETTE; 4],
+            attributes: [0; CELLS_X * CELLS_Y],
+            mask: Mask::None,
+            transfer: None,
+            border_tiles: vec![0; 2 * TRANSFER_SIZE].into_boxed_slice(),
+            border_map: vec![0; 0x800].into_boxed_slice(),
+            border_palettes: [[0; 16]; 4],
+            shades: vec![0; WIDTH * HEIGHT].into_boxed_slice(),
+            lcd: ppu::new_buffer(),
+            frame: new_buffer(),
+            writer: None,
+        }
+    }
+}
+
+impl Sgb {
+    pub fn set_writer(&mut self, writer: Writer<SgbFrameBuffer>) {
+        self.writer = Some(writer);
+    }
+
+    // keeps the shade the ppu drew at `index` of the lcd and returns the colour it's
+    // shown as, according to the palettes and attributes in effect right now
+    pub fn draw(&mut self, index: usize, shade: u8) -> RGBA8888 {
+        self.shades[index] = shade & 0b11;
+        self.color(index, shade)
+    }
+
+    // what a blank lcd looks like, colour 0 everywhere
+    pub fn clear(&mut self) {
+        self.shades.fill(0);
+    }
+
+    fn color(&self, index: usize, shade: u8) -> RGBA8888 {
+        let (x, y) = (index % WIDTH, index / WIDTH);
+        let palette = self.attributes[(y / 8) * CELLS_X + x / 8] as usize;
+        rgba_from_rgb555(self.palettes[palette][shade as usize & 0b11])
+    }
+
+    // a shade in the first palette, for showing tiles outside of the lcd
+    pub fn palette_color(&self, shade: u8) -> RGBA8888 {
+        rgba_from_rgb555(self.palettes[0][shade as usize & 0b11])
+    }
+
+    pub fn is_waiting_for_transfer(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    // the first packet of a command says how many packets it spans
+    pub fn receive(&mut self, packet: &Packet) {
+        if self.packets_left == 0 {
+            self.command.clear();
+            self.packets_left = (packet[0] & 0b111).max(1);
+        }
+        self.command.extend_from_slice(packet);
+        self.packets_left -= 1;
+
+        if self.packets_left == 0 {
+            let command = std::mem::take(&mut self.command);
+            self.run(&command);
+            self.command = command;
+        }
+    }
+
+    fn run(&mut self, data: &[u8]) {
+        match data[0] >> 3 {
+            PAL01 => self.set_palettes(0, 1, data),
+            PAL23 => self.set_palettes(2, 3, data),
+            PAL03 => self.set_palettes(0, 3, data),
+            PAL12 => self.set_palettes(1, 2, data),
+            ATTR_BLK => self.attribute_blocks(data),
+            ATTR_LIN => self.attribute_lines(data),
+            ATTR_DIV => self.attribute_division(data),
+            ATTR_CHR => self.attribute_cells(data),
+            CHR_TRN => self.transfer = Some(Transfer::Tiles(data[1] & 1 != 0)),
+            PCT_TRN => self.transfer = Some(Transfer::Border),
+            MASK_EN => {
+                self.mask = match data[1] & 0b11 {
+                    0 => Mask::None,
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    _ => Mask::Color0,
+                }
+            }
+            // MLT_REQ only concerns the joypad, which handles it itself
+            MLT_REQ => (),
+            command => eprintln!("unsupported sgb command {:#04X}", command),
+        }
+    }
+
+    // colour 0 is shared by all palettes, so setting it for one sets it for all of them
+    fn set_palettes(&mut self, first: usize, second: usize, data: &[u8]) {
+        let color = |index: usize| u16::from_le_bytes([data[1 + index * 2], data[2 + index * 2]]);
+
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color(0);
+        }
+        for index in 1..4 {
+            self.palettes[first][index] = color(index);
+            self.palettes[second][index] = color(index + 3);
+        }
+    }
+
+    fn set_attribute(&mut self, x: usize, y: usize, palette: u8) {
+        if x < CELLS_X && y < CELLS_Y {
+            self.attributes[y * CELLS_X + x] = palette & 0b11;
+        }
+    }
+
+    // rectangles, with separate palettes for the inside, the outline and the outside
+    fn attribute_blocks(&mut self, data: &[u8]) {
+        let count = (data[1] & 0x1F) as usize;
+
+        for block in data[2..].chunks_exact(6).take(count) {
+            let control = block[0] & 0b111;
+            let palettes = block[1];
+            let (left, top, right, bottom) = (
+                block[2] as usize,
+                block[3] as usize,
+                block[4] as usize,
+                block[5] as usize,
+            );
+
+            let inside = palettes & 0b11;
+            let outside = (palettes >> 4) & 0b11;
+            // with only the inside or the outside given, the outline goes along with it
+            let (has_outline, outline) = match control {
+                0b001 => (true, inside),
+                0b100 => (true, outside),
+                _ => (control & 0b010 != 0, (palettes >> 2) & 0b11),
+            };
+
+            for y in 0..CELLS_Y {
+                for x in 0..CELLS_X {
+                    let is_within = (left..=right).contains(&x) && (top..=bottom).contains(&y);
+                    let is_inside = x > left && x < right && y > top && y < bottom;
+
+                    if is_inside && control & 0b001 != 0 {
+                        self.set_attribute(x, y, inside);
+                    } else if is_within && !is_inside && has_outline {
+                        self.set_attribute(x, y, outline);
+                    } else if !is_within && control & 0b100 != 0 {
+                        self.set_attribute(x, y, outside);
+                    }
+                }
+            }
+        }
+    }
+
+    // whole rows or columns of cells
+    fn attribute_lines(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+
+        for &line in data[2..].iter().take(count) {
+            let number = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 0b11;
+            if line & 0x80 != 0 {
+                for x in 0..CELLS_X {
+                    self.set_attribute(x, number, palette);
+                }
+            } else {
+                for y in 0..CELLS_Y {
+                    self.set_attribute(number, y, palette);
+                }
+            }
+        }
+    }
+
+    // splits the screen in two along a row or column, which gets a palette of its own
+    fn attribute_division(&mut self, data: &[u8]) {
+        let after = data[1] & 0b11;
+        let before = (data[1] >> 2) & 0b11;
+        let on = (data[1] >> 4) & 0b11;
+        let is_horizontal = data[1] & 0x40 != 0;
+        let line = (data[2] & 0x1F) as usize;
+
+        for y in 0..CELLS_Y {
+            for x in 0..CELLS_X {
+                let position = if is_horizontal { y } else { x };
+                let palette = match position.cmp(&line) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on,
+                    std::cmp::Ordering::Greater => after,
+                };
+                self.set_attribute(x, y, palette);
+            }
+        }
+    }
+
+    // individual cells, starting at a given one and going left to right or top to bottom
+    fn attribute_cells(&mut self, data: &[u8]) {
+        let (mut x, mut y) = (data[1] as usize, data[2] as usize);
+        let count = u16::from_le_bytes([data[3], data[4]]) as usize;
+        let is_vertical = data[5] & 1 != 0;
+
+        // four palettes per byte, highest bits first
+        let palettes = data[6..]
+            .iter()
+            .flat_map(|&byte| (0..4).rev().map(move |shift| (byte >> (shift * 2)) & 0b11));
+
+        for palette in palettes.take(count) {
+            if x >= CELLS_X || y >= CELLS_Y {
+                break;
+            }
+            self.set_attribute(x, y, palette);
+
+            if is_vertical {
+                y += 1;
+                if y == CELLS_Y {
+                    y = 0;
+                    x += 1;
+                }
+            } else {
+                x += 1;
+                if x == CELLS_X {
+                    x = 0;
+                    y += 1;
+                }
+            }
+        }
+    }
+
+    // `data` is what was on screen during the frame after a transfer command, read as
+    // tile data
+    pub fn transfer(&mut self, data: &[u8]) {
+        match self.transfer.take() {
+            Some(Transfer::Tiles(is_upper)) => {
+                let start = if is_upper { TRANSFER_SIZE } else { 0 };
+                self.border_tiles[start..start + TRANSFER_SIZE].copy_from_slice(data);
+            }
+            Some(Transfer::Border) => {
+                self.border_map.copy_from_slice(&data[..0x800]);
+                for (index, palette) in self.border_palettes.iter_mut().enumerate() {
+                    for (color_index, color) in palette.iter_mut().enumerate() {
+                        let offset = 0x800 + index * 32 + color_index * 2;
+                        *color = u16::from_le_bytes([data[offset], data[offset + 1]]);
+                    }
+                }
+            }
+            None => (),
+        }
+    }
+
+    // colours in the shades the ppu drew this frame into `frame`, and composes the
+    // bordered frame around it
+    pub fn complete_frame(&mut self, frame: &mut FrameBuffer) {
+        let color0 = rgba_from_rgb555(self.palettes[0][0]);
+
+        match self.mask {
+            Mask::None => {
+                for (index, pixel) in frame.iter_mut().enumerate() {
+                    *pixel = self.color(index, self.shades[index]);
+                }
+                self.lcd.copy_from_slice(&frame[..]);
+            }
+            Mask::Freeze => frame.copy_from_slice(&self.lcd[..]),
+            Mask::Black => frame.fill(0x000000FF),
+            Mask::Color0 => frame.fill(color0),
+        }
+
+        self.draw_border(color0);
+        for y in 0..HEIGHT {
+            let start = (LCD_Y + y) * BORDER_WIDTH + LCD_X;
+            self.frame[start..start + WIDTH].copy_from_slice(&frame[y * WIDTH..(y + 1) * WIDTH]);
+        }
+
+        if let Some(writer) = &mut self.writer {
+            writer.back_mut().copy_from_slice(&self.frame[..]);
+            writer.publish();
+        }
+    }
+
+    fn draw_border(&mut self, backdrop: RGBA8888) {
+        for tile_y in 0..BORDER_HEIGHT / 8 {
+            for tile_x in 0..BORDER_WIDTH / 8 {
+                let offset = (tile_y * 32 + tile_x) * 2;
+                let entry =
+                    u16::from_le_bytes([self.border_map[offset], self.border_map[offset + 1]]);
+
+                let tile = &self.border_tiles[(entry & 0xFF) as usize * 32..][..32];
+                // the border uses snes palettes 4-7
+                let palette = &self.border_palettes[((entry >> 10) & 0b11) as usize];
+                let is_x_flipped = entry & (1 << 14) != 0;
+                let is_y_flipped = entry & (1 << 15) != 0;
+
+                for row in 0..8 {
+                    let source_row = if is_y_flipped { 7 - row } else { row };
+                    for column in 0..8 {
+                        let bit = if is_x_flipped { column } else { 7 - column };
+                        // bitplanes 0 and 1 are interleaved in the first half, 2 and 3 in
+                        // the second one
+                        let color_index = (0..4).fold(0, |index, plane| {
+                            let byte = tile[(plane / 2) * 16 + source_row * 2 + plane % 2];
+                            index | ((byte >> bit) & 1) << plane
+                        });
+
+                        let color = if color_index == 0 {
+                            backdrop
+                        } else {
+                            rgba_from_rgb555(palette[color_index as usize])
+                        };
+                        let (x, y) = (tile_x * 8 + column, tile_y * 8 + row);
+                        self.frame[y * BORDER_WIDTH + x] = color;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a single packet command, with the rest of it zeroed
+    fn packet(command: u8, data: &[u8]) -> Packet {
+        let mut packet = [0; PACKET_SIZE];
+        packet[0] = command << 3 | 1;
+        packet[1..1 + data.len()].copy_from_slice(data);
+        packet
+    }
+
+    fn attribute(sgb: &Sgb, x: usize, y: usize) -> u8 {
+        sgb.attributes[y * CELLS_X + x]
+    }
+
+    #[test]
+    fn palette_commands_share_colour_0() {
+        let mut sgb = Sgb::default();
+        // colour 0, then colours 1-3 of both palettes
+        let data = [
+            0x11, 0x11, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x04, 0x00, 0x05, 0x00, 0x06, 0x00,
+        ];
+        sgb.receive(&packet(PAL12, &data));
+
+        for palette in sgb.palettes {
+            assert_eq!(palette[0], 0x1111);
+        }
+        assert_eq!(sgb.palettes[1], [0x1111, 1, 2, 3]);
+        assert_eq!(sgb.palettes[2], [0x1111, 4, 5, 6]);
+        assert_eq!(sgb.palettes[0][1..], DEFAULT_PALETTE[1..]);
+    }
+
+    #[test]
+    fn commands_wait_for_all_their_packets() {
+        let mut sgb = Sgb::default();
+        // ATTR_LIN over two packets: 15 lines, the last of which is in the second one
+        let mut first = packet(ATTR_LIN, &[15]);
+        first[0] = ATTR_LIN << 3 | 2;
+        for (index, line) in first[2..].iter_mut().enumerate() {
+            *line = 0x80 | 0b0010_0000 | index as u8;
+        }
+        sgb.receive(&first);
+        assert_eq!(attribute(&sgb, 0, 0), 0);
+
+        let mut second = [0; PACKET_SIZE];
+        second[0] = 0x80 | 0b0100_0000 | 17;
+        sgb.receive(&second);
+        assert_eq!(attribute(&sgb, 0, 0), 1);
+        assert_eq!(attribute(&sgb, 19, 13), 1);
+        assert_eq!(attribute(&sgb, 0, 14), 0);
+        assert_eq!(attribute(&sgb, 5, 17), 2);
+    }
+
+    #[test]
+    fn attribute_blocks_set_inside_outline_and_outside() {
+        let mut sgb = Sgb::default();
+        // one block from (2, 2) to (5, 5): inside 1, outline 2, outside 3
+        sgb.receive(&packet(ATTR_BLK, &[1, 0b111, 0b11_10_01, 2, 2, 5, 5]));
+
+        assert_eq!(attribute(&sgb, 3, 3), 1);
+        assert_eq!(attribute(&sgb, 2, 4), 2);
+        assert_eq!(attribute(&sgb, 5, 5), 2);
+        assert_eq!(attribute(&sgb, 6, 3), 3);
+        assert_eq!(attribute(&sgb, 0, 0), 3);
+    }
+
+    #[test]
+    fn attribute_blocks_with_only_the_inside_colour_the_outline_too() {
+        let mut sgb = Sgb::default();
+        sgb.receive(&packet(ATTR_BLK, &[1, 0b001, 0b11_10_01, 2, 2, 5, 5]));
+
+        assert_eq!(attribute(&sgb, 3, 3), 1);
+        assert_eq!(attribute(&sgb, 2, 2), 1);
+        assert_eq!(attribute(&sgb, 6, 3), 0);
+    }
+
+    #[test]
+    fn attribute_division_splits_around_a_line() {
+        let mut sgb = Sgb::default();
+        // horizontal, on row 9: before 1, on 2, after 3
+        sgb.receive(&packet(ATTR_DIV, &[0x40 | 0b10_01_11, 9]));
+
+        assert_eq!(attribute(&sgb, 4, 8), 1);
+        assert_eq!(attribute(&sgb, 4, 9), 2);
+        assert_eq!(attribute(&sgb, 4, 10), 3);
+    }
+
+    #[test]
+    fn attribute_cells_wrap_around_to_the_next_row() {
+        let mut sgb = Sgb::default();
+        // three cells from (19, 0), left to right
+        sgb.receive(&packet(ATTR_CHR, &[19, 0, 3, 0, 0, 0b01_10_11_00]));
+
+        assert_eq!(attribute(&sgb, 19, 0), 1);
+        assert_eq!(attribute(&sgb, 0, 1), 2);
+        assert_eq!(attribute(&sgb, 1, 1), 3);
+        assert_eq!(attribute(&sgb, 2, 1), 0);
+    }
+
+    #[test]
+    fn transfers_wait_for_the_next_frame() {
+        let mut sgb = Sgb::default();
+        sgb.receive(&packet(CHR_TRN, &[1]));
+        assert!(sgb.is_waiting_for_transfer());
+
+        sgb.transfer(&[0xAB; TRANSFER_SIZE]);
+        assert!(!sgb.is_waiting_for_transfer());
+        assert_eq!(sgb.border_tiles[TRANSFER_SIZE], 0xAB);
+        assert_eq!(sgb.border_tiles[0], 0);
+    }
+
+    #[test]
+    fn shades_are_coloured_by_the_palette_of_their_cell() {
+        let mut sgb = Sgb::default();
+        sgb.receive(&packet(ATTR_DIV, &[0b00_00_01, 0]));
+        sgb.receive(&packet(
+            PAL01,
+            &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x1F, 0],
+        ));
+
+        // column 0 is on the line, everything after it uses palette 1
+        assert_eq!(sgb.draw(0, 3), 0x000000FF);
+        assert_eq!(sgb.draw(8, 3), 0xFF0000FF);
+
+        let mut frame = ppu::new_buffer();
+        sgb.complete_frame(&mut frame);
+        assert_eq!(frame[8], 0xFF0000FF);
+        // anything that wasn't drawn is still colour 0
+        assert_eq!(frame[9], 0x000000FF);
+    }
+
+    #[test]
+    fn masks_hide_the_lcd() {
+        let mut sgb = Sgb::default();
+        let mut frame = ppu::new_buffer();
+        sgb.draw(0, 3);
+        sgb.complete_frame(&mut frame);
+        let shown = frame[0];
+
+        sgb.receive(&packet(MASK_EN, &[1]));
+        sgb.clear();
+        sgb.complete_frame(&mut frame);
+        assert_eq!(frame[0], shown);
+
+        sgb.receive(&packet(MASK_EN, &[2]));
+        sgb.complete_frame(&mut frame);
+        assert_eq!(frame[0], 0x000000FF);
+
+        sgb.receive(&packet(MASK_EN, &[3]));
+        sgb.complete_frame(&mut frame);
+        assert_eq!(frame[0], rgba_from_rgb555(DEFAULT_PALETTE[0]));
+    }
+}
@@ -1,6 +1,12 @@
 use crate::emulator::Emulator;
 use crate::emulator::host::handle::Handle;
-use std::{env, fs, sync::mpsc::TryRecvError};
+use crate::emulator::host::postprocess::{PostProcess, Scaler, Settings};
+use crate::emulator::host::{DriverMessage, EmulatorMessage, screenshot};
+use crate::emulator::runtime::model::Model;
+use crate::emulator::runtime::ppu::debug::Image;
+use crate::emulator::runtime::ppu::palette::{self, Palettes, Preset};
+use crate::emulator::runtime::ppu::{HEIGHT, RGBA8888, WIDTH};
+use std::{env, fs, path::PathBuf, process, sync::mpsc::TryRecvError};
 
 pub mod emulator;
 
@@ -8,10 +14,27 @@ pub mod emulator;
 
 struct BamegoyApp {
     emulator_handle: Handle,
+    post_process: PostProcess,
+    // the last frame after post processing, which is what would be on screen. only scaled
+    // up when it's needed, completing a frame just clears it
+    screen: Option<Image>,
+    // integer factor screenshots are saved at
+    scale: usize,
+    // the number of the last completed frame, screenshots taken from the window are named
+    // after it
+    frame: u64,
+    is_running: bool,
+    texture: Option<egui::TextureHandle>,
 }
 
 impl BamegoyApp {
-    pub fn new(rom_filepath: Option<String>, should_trace_log: bool) -> Self {
+    pub fn new(
+        rom_filepath: Option<String>,
+        should_trace_log: bool,
+        model: Option<Model>,
+        post_process: Settings,
+        scale: usize,
+    ) -> Self {
         let cartridge_rom: Vec<u8> = match rom_filepath {
             Some(p) => match fs::read(&p) {
                 Err(e) => {
@@ -23,12 +46,146 @@ impl BamegoyApp {
             None => vec![0; 0x8000],
         };
 
-        let handle = Emulator::init(cartridge_rom, should_trace_log);
+        let handle = Emulator::init(cartridge_rom, should_trace_log, model);
 
         Self {
             emulator_handle: handle,
+            post_process: PostProcess::new(post_process),
+            screen: None,
+            scale,
+            frame: 0,
+            is_running: false,
+            texture: None,
         }
     }
+
+    // blends the frame FrameCompleted announced with the previous ones. if the emulator
+    // already published a newer one, the announced frame is gone and gets skipped.
+    fn present(&mut self, frame: u64) {
+        let latest = self.emulator_handle.frames.latest();
+        if latest.number != Some(frame) {
+            return;
+        }
+        self.post_process.push(&latest.pixels);
+        self.screen = None;
+    }
+
+    fn screen(&mut self) -> Option<&Image> {
+        if !self.post_process.settings().is_enabled() {
+            return None;
+        }
+        if self.screen.is_none() {
+            self.screen = self.post_process.scaled();
+        }
+        self.screen.as_ref()
+    }
+
+    // the core only knows about raw frames, post processed ones are saved here. returns
+    // whether the screenshot was saved, otherwise the core was asked to save it.
+    fn save_screenshot(&mut self, path: PathBuf, scale: usize) -> bool {
+        match self.screen() {
+            Some(screen) => {
+                // a scaler already picked the size, `scale` only applies when there's none
+                let scale = if screen.width > WIDTH { 1 } else { scale };
+                let png = screenshot::encode(&screen.pixels, screen.width, screen.height, scale);
+                match fs::write(&path, png) {
+                    Ok(()) => println!("saved screenshot to {:?}", path),
+                    Err(e) => eprintln!("failed to save screenshot to {:?}: {}", path, e),
+                }
+                true
+            }
+            _ => {
+                self.emulator_handle
+                    .tx
+                    .send(DriverMessage::Screenshot { path, scale })
+                    .unwrap();
+                false
+            }
+        }
+    }
+
+    fn handle_message(&mut self, message: EmulatorMessage) {
+        match message {
+            EmulatorMessage::FrameCompleted { frame, .. } => {
+                self.frame = frame;
+                // post processing needs to see every frame, blending relies on it
+                if self.post_process.settings().is_enabled() {
+                    self.present(frame);
+                }
+            }
+            EmulatorMessage::Running => self.is_running = true,
+            EmulatorMessage::Paused => self.is_running = false,
+            EmulatorMessage::ScreenshotSaved(path) => println!("saved screenshot to {:?}", path),
+            message => println!("{:?}", message),
+        }
+    }
+
+    // what's on screen right now, as an egui image
+    fn screen_image(&mut self) -> egui::ColorImage {
+        let (pixels, width, height): (&[RGBA8888], _, _) = match self.screen() {
+            Some(screen) => (&screen.pixels, screen.width, screen.height),
+            None => (
+                &self.emulator_handle.frames.latest().pixels[..],
+                WIDTH,
+                HEIGHT,
+            ),
+        };
+        let bytes: Vec<u8> = pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_be_bytes())
+            .collect();
+        egui::ColorImage::from_rgba_unmultiplied([width, height], &bytes)
+    }
+}
+
+impl eframe::App for BamegoyApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Ok(message) = self.emulator_handle.rx.try_recv() {
+            self.handle_message(message);
+        }
+
+        let mut should_take_screenshot = ctx.input(|input| input.key_pressed(egui::Key::F12));
+        egui::TopBottomPanel::top("controls").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let (label, message) = if self.is_running {
+                    ("Pause", DriverMessage::PauseRequest)
+                } else {
+                    ("Run", DriverMessage::Run(None))
+                };
+                if ui.button(label).clicked() {
+                    self.emulator_handle.tx.send(message).unwrap();
+                }
+                should_take_screenshot |= ui.button("Screenshot").on_hover_text("F12").clicked();
+            });
+        });
+        if should_take_screenshot {
+            let path = PathBuf::from(format!("bamegoy-{}.png", self.frame));
+            self.save_screenshot(path, self.scale);
+        }
+
+        // pixels stay sharp, at the largest integer scale that fits the window
+        let image = self.screen_image();
+        let texture = match &mut self.texture {
+            Some(texture) => {
+                texture.set(image, egui::TextureOptions::NEAREST);
+                texture
+            }
+            None => self.texture.insert(ctx.load_texture(
+                "screen",
+                image,
+                egui::TextureOptions::NEAREST,
+            )),
+        };
+        let (id, size) = (texture.id(), texture.size_vec2());
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let factor = (ui.available_size() / size).min_elem().floor().max(1.0);
+            ui.centered_and_justified(|ui| {
+                ui.image(egui::load::SizedTexture::new(id, size * factor));
+            });
+        });
+
+        ctx.request_repaint();
+    }
 }
 
 fn main() {
@@ -41,13 +198,85 @@ fn main() {
     let rom_filepath = args.pop();
 
     let mut should_trace_log = false;
-    for arg in args.into_iter().skip(1) {
-        if arg == "--trace" {
-            should_trace_log = true;
+    let mut palettes = None;
+    let mut model = None;
+    // frames to run for before taking a screenshot, and where to put it
+    let mut screenshot = None;
+    let mut scale = 1;
+    let mut post_process = Settings::default();
+    let mut args = args.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => should_trace_log = true,
+            "--palette" => match args.next().map(|name| name.parse::<Preset>()) {
+                Some(Ok(preset)) => palettes = Some(Palettes::uniform(preset.palette())),
+                Some(Err(e)) => eprintln!("{}", e),
+                None => eprintln!("--palette expects a preset name"),
+            },
+            "--model" => match args.next().map(|name| name.parse::<Model>()) {
+                Some(Ok(m)) => model = Some(m),
+                Some(Err(e)) => eprintln!("{}", e),
+                None => eprintln!("--model expects dmg, cgb or sgb"),
+            },
+            "--screenshot" => {
+                match (args.next().map(|frames| frames.parse::<u64>()), args.next()) {
+                    (Some(Ok(frames)), Some(path)) => {
+                        screenshot = Some((frames, PathBuf::from(path)))
+                    }
+                    _ => eprintln!("--screenshot expects a number of frames and a path"),
+                }
+            }
+            "--scale" => match args.next().map(|s| s.parse::<usize>()) {
+                Some(Ok(s)) if s > 0 => scale = s,
+                _ => eprintln!("--scale expects a positive integer"),
+            },
+            "--color-correction" => post_process.is_color_corrected = true,
+            "--blend" => match args.next().map(|p| p.parse::<f32>()) {
+                Some(Ok(p)) if (0.0..1.0).contains(&p) => post_process.persistence = p,
+                _ => eprintln!("--blend expects a persistence between 0 and 1"),
+            },
+            "--scaler" => match args.next().map(|name| name.parse::<Scaler>()) {
+                Some(Ok(scaler)) => post_process.scaler = scaler,
+                Some(Err(e)) => eprintln!("{}", e),
+                None => eprintln!("--scaler expects 2x, 3x, ..., scale2x, scale3x or xbr2x"),
+            },
+            "--palette-file" => match args.next().map(fs::read_to_string) {
+                Some(Ok(config)) => match palette::parse_palettes(&config) {
+                    Ok(p) => palettes = Some(p),
+                    Err(e) => eprintln!("failed to parse palette file: {}", e),
+                },
+                Some(Err(e)) => eprintln!("failed to read palette file: {}", e),
+                None => eprintln!("--palette-file expects a path"),
+            },
+            _ => (),
         }
     }
 
-    let app = BamegoyApp::new(rom_filepath, should_trace_log);
+    let mut app = BamegoyApp::new(rom_filepath, should_trace_log, model, post_process, scale);
+    if let Some(palettes) = palettes {
+        app.emulator_handle
+            .tx
+            .send(DriverMessage::SetPalettes(palettes))
+            .unwrap();
+    }
+
+    // with a screenshot to take, this runs headless: until the frame is reached and saved.
+    // otherwise it opens a window
+    if screenshot.is_none() {
+        let result = eframe::run_native(
+            "bamegoy",
+            eframe::NativeOptions::default(),
+            Box::new(|_| Ok(Box::new(app))),
+        );
+        if let Err(e) = result {
+            eprintln!("failed to open a window: {}", e);
+        }
+        return;
+    }
+    app.emulator_handle
+        .tx
+        .send(DriverMessage::Run(None))
+        .unwrap();
 
     loop {
         let message = match app.emulator_handle.rx.try_recv() {
@@ -59,6 +288,28 @@ fn main() {
                 panic!("{}", err)
             }
         };
-        println!("{:?}", message);
+        // post processing needs to see every frame, blending relies on it
+        let is_post_processed = app.post_process.settings().is_enabled();
+        if is_post_processed && let EmulatorMessage::FrameCompleted { frame, .. } = message {
+            app.present(frame);
+        }
+
+        match &message {
+            EmulatorMessage::FrameCompleted { frame, .. }
+                if screenshot
+                    .as_ref()
+                    .is_some_and(|(frames, _)| frame >= frames) =>
+            {
+                let (_, path) = screenshot.take().unwrap();
+                if app.save_screenshot(path, scale) {
+                    process::exit(0);
+                }
+            }
+            EmulatorMessage::ScreenshotSaved(path) => {
+                println!("saved screenshot to {:?}", path);
+                process::exit(0);
+            }
+            _ => println!("{:?}", message),
+        }
     }
 }